@@ -0,0 +1,231 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::Arc;
+use tauri::{plugin::TauriPlugin, AppHandle, Runtime};
+
+use crate::handler::TaskRouter;
+use crate::{Config, IntoTaskHandler, TaskContext, TaskHandler, TaskKind, TaskParameters, TaskStore};
+
+/// Builds the plugin with one handler per task name.
+///
+/// ```rust,ignore
+/// tauri_plugin_schedule_task::Builder::new()
+///   .handler("backup", |parameters, _app| run_backup(&parameters))
+///   .async_handler("sync", |parameters, app| async move { sync(parameters, app).await })
+///   .fallback(MyTaskHandler)
+///   .build()
+/// ```
+///
+/// `schedule_task` fails with [`crate::Error::UnknownTask`] for the names without a handler,
+/// unless a fallback handler is set.
+pub struct Builder<R: Runtime> {
+  routes: HashMap<String, TaskHandler<R>>,
+  fallback: Option<TaskHandler<R>>,
+  store: Option<Arc<dyn TaskStore>>,
+}
+
+impl<R: Runtime> Default for Builder<R> {
+  fn default() -> Self {
+    Self {
+      routes: HashMap::new(),
+      fallback: None,
+      store: None,
+    }
+  }
+}
+
+impl<R: Runtime> Builder<R> {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Runs `handler` for the tasks named `task_name`.
+  pub fn handler<F>(self, task_name: impl Into<String>, handler: F) -> Self
+  where
+    F: Fn(TaskParameters, &AppHandle<R>) -> crate::Result<()> + Send + Sync + 'static,
+  {
+    self.handler_with_context(task_name, move |_context, parameters, app| handler(parameters, app))
+  }
+
+  /// Runs `handler` for the tasks named `task_name`, with the [`TaskContext`] of the run.
+  pub fn handler_with_context<F>(mut self, task_name: impl Into<String>, handler: F) -> Self
+  where
+    F: Fn(&TaskContext, TaskParameters, &AppHandle<R>) -> crate::Result<()> + Send + Sync + 'static,
+  {
+    self.routes.insert(task_name.into(), TaskHandler::from_fn(handler));
+    self
+  }
+
+  /// Runs the future returned by `handler` for the tasks named `task_name`.
+  pub fn async_handler<F, Fut>(self, task_name: impl Into<String>, handler: F) -> Self
+  where
+    F: Fn(TaskParameters, AppHandle<R>) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = crate::Result<()>> + Send + 'static,
+  {
+    self.async_handler_with_context(task_name, move |_context, parameters, app| handler(parameters, app))
+  }
+
+  /// Runs the future returned by `handler` for the tasks named `task_name`, with the
+  /// [`TaskContext`] of the run.
+  pub fn async_handler_with_context<F, Fut>(mut self, task_name: impl Into<String>, handler: F) -> Self
+  where
+    F: Fn(TaskContext, TaskParameters, AppHandle<R>) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = crate::Result<()>> + Send + 'static,
+  {
+    self.routes.insert(task_name.into(), TaskHandler::from_async_fn(handler));
+    self
+  }
+
+  /// Runs `handler` for the tasks of kind `K`, with their parameters parsed as `K::Params`.
+  pub fn task<K: TaskKind>(
+    self,
+    handler: impl Fn(K::Params, &AppHandle<R>) -> crate::Result<()> + Send + Sync + 'static,
+  ) -> Self {
+    self.task_with_context::<K>(move |_context, params, app| handler(params, app))
+  }
+
+  /// Runs `handler` for the tasks of kind `K`, with the [`TaskContext`] of the run and their
+  /// parameters parsed as `K::Params`.
+  pub fn task_with_context<K: TaskKind>(
+    self,
+    handler: impl Fn(&TaskContext, K::Params, &AppHandle<R>) -> crate::Result<()> + Send + Sync + 'static,
+  ) -> Self {
+    self.handler_with_context(K::NAME, move |context, parameters, app| handler(context, parameters.parse()?, app))
+  }
+
+  /// Runs the future returned by `handler` for the tasks of kind `K`, with their parameters parsed as `K::Params`.
+  pub fn async_task<K: TaskKind, Fut>(
+    self,
+    handler: impl Fn(K::Params, AppHandle<R>) -> Fut + Send + Sync + 'static,
+  ) -> Self
+  where
+    Fut: Future<Output = crate::Result<()>> + Send + 'static,
+  {
+    self.async_task_with_context::<K, Fut>(move |_context, params, app| handler(params, app))
+  }
+
+  /// Runs the future returned by `handler` for the tasks of kind `K`, with the [`TaskContext`] of
+  /// the run and their parameters parsed as `K::Params`.
+  pub fn async_task_with_context<K: TaskKind, Fut>(
+    self,
+    handler: impl Fn(TaskContext, K::Params, AppHandle<R>) -> Fut + Send + Sync + 'static,
+  ) -> Self
+  where
+    Fut: Future<Output = crate::Result<()>> + Send + 'static,
+  {
+    self.async_handler_with_context(K::NAME, move |context, parameters, app| {
+      let run = parameters.parse().map(|params| handler(context, params, app));
+      async move { run?.await }
+    })
+  }
+
+  /// Handles the tasks whose name has no handler of its own.
+  pub fn fallback<H: IntoTaskHandler<R, K>, K>(mut self, handler: H) -> Self {
+    self.fallback = Some(handler.into_task_handler());
+    self
+  }
+
+  /// Keeps the tasks in `store` instead of the default store (desktop only).
+  pub fn store<S: TaskStore + 'static>(mut self, store: S) -> Self {
+    self.store = Some(Arc::new(store));
+    self
+  }
+
+  pub fn build(self) -> TauriPlugin<R, Option<Config>> {
+    let (handler, store) = self.into_parts();
+    crate::build(Some(handler), store)
+  }
+
+  /// The handler routing tasks to the registered handlers, and the store.
+  fn into_parts(self) -> (TaskHandler<R>, Option<Arc<dyn TaskStore>>) {
+    let router = TaskRouter {
+      routes: self.routes,
+      fallback: self.fallback,
+    };
+    (TaskHandler::router(router), self.store)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use serde::{Deserialize, Serialize};
+  use std::sync::Mutex;
+  use tauri::test::{mock_app, MockRuntime};
+
+  struct Backup;
+
+  #[derive(Serialize, Deserialize)]
+  struct BackupParams {
+    target: String,
+  }
+
+  impl TaskKind for Backup {
+    const NAME: &'static str = "backup";
+    type Params = BackupParams;
+  }
+
+  fn handle(builder: Builder<MockRuntime>, task_name: &str, parameters: serde_json::Value) -> crate::Result<()> {
+    let app = mock_app();
+    let (handler, _) = builder.into_parts();
+    let context = TaskContext::new("1".to_string(), task_name.to_string());
+    let parameters = serde_json::from_value(parameters).unwrap();
+    tauri::async_runtime::block_on(handler.handle(&context, parameters, app.handle()))
+  }
+
+  #[test]
+  fn routes_tasks_by_name() {
+    let handled = Arc::new(Mutex::new(Vec::new()));
+    let builder = |handled: &Arc<Mutex<Vec<String>>>| {
+      let (cleanup, report) = (handled.clone(), handled.clone());
+      Builder::new()
+        .handler("cleanup", move |_parameters, _app| {
+          cleanup.lock().unwrap().push("cleanup".to_string());
+          Ok(())
+        })
+        .async_handler_with_context("report", move |context, _parameters, _app| {
+          let report = report.clone();
+          async move {
+            report.lock().unwrap().push(format!("report {}", context.task_id));
+            Ok(())
+          }
+        })
+    };
+    handle(builder(&handled), "report", serde_json::json!({})).unwrap();
+    handle(builder(&handled), "cleanup", serde_json::json!({})).unwrap();
+    assert_eq!(*handled.lock().unwrap(), ["report 1", "cleanup"]);
+  }
+
+  #[test]
+  fn parses_the_parameters_of_task_kinds() {
+    let targets = Arc::new(Mutex::new(Vec::new()));
+    let builder = || {
+      let targets = targets.clone();
+      Builder::new().task::<Backup>(move |params, _app| {
+        targets.lock().unwrap().push(params.target);
+        Ok(())
+      })
+    };
+    handle(builder(), "backup", serde_json::json!({ "target": "nas" })).unwrap();
+    assert!(matches!(handle(builder(), "backup", serde_json::json!({ "target": 7 })), Err(crate::Error::InvalidParameters(_))));
+    assert_eq!(*targets.lock().unwrap(), ["nas"]);
+  }
+
+  #[test]
+  fn hands_unknown_names_to_the_fallback() {
+    let builder = || Builder::new().handler("cleanup", |_parameters, _app| Ok(()));
+    assert!(matches!(handle(builder(), "backup", serde_json::json!({})), Err(crate::Error::UnknownTask(name)) if name == "backup"));
+    let result = handle(builder().fallback(Fallback), "backup", serde_json::json!({}));
+    assert!(matches!(result, Err(crate::Error::Generic(message)) if message == "fallback ran backup"));
+    assert!(handle(builder().fallback(Fallback), "cleanup", serde_json::json!({})).is_ok());
+  }
+
+  /// Fails every task, telling which one it was given.
+  struct Fallback;
+
+  impl crate::ScheduledTaskHandler<MockRuntime> for Fallback {
+    fn handle_scheduled_task(&self, task_name: &str, _parameters: TaskParameters, _app: &AppHandle<MockRuntime>) -> crate::Result<()> {
+      Err(crate::Error::Generic(format!("fallback ran {}", task_name)))
+    }
+  }
+}
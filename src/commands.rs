@@ -1,8 +1,9 @@
-use tauri::{AppHandle, command, Runtime};
+use tauri::{ipc::Channel, AppHandle, command, Manager, Runtime};
 
 use crate::models::*;
 use crate::Result;
 use crate::ScheduleTaskExt;
+use crate::events::TaskWatchers;
 
 #[command]
 pub(crate) async fn schedule_task<R: Runtime>(
@@ -20,12 +21,59 @@ pub(crate) async fn schedule_task<R: Runtime>(
     //foda
 }
 
+#[command]
+pub(crate) async fn update_task<R: Runtime>(
+    app: AppHandle<R>,
+    payload: UpdateTaskRequest,
+) -> Result<UpdateTaskResponse> {
+    app.schedule_task().update_task(payload).await
+}
+
+#[command]
+pub(crate) async fn pause_task<R: Runtime>(
+    app: AppHandle<R>,
+    payload: PauseTaskRequest,
+) -> Result<UpdateTaskResponse> {
+    app.schedule_task().pause_task(payload).await
+}
+
+#[command]
+pub(crate) async fn resume_task<R: Runtime>(
+    app: AppHandle<R>,
+    payload: ResumeTaskRequest,
+) -> Result<UpdateTaskResponse> {
+    app.schedule_task().resume_task(payload).await
+}
+
+#[command]
+pub(crate) async fn run_task_now<R: Runtime>(
+    app: AppHandle<R>,
+    payload: RunTaskNowRequest,
+) -> Result<RunTaskNowResponse> {
+    app.schedule_task().run_now(payload).await
+}
+
 #[command]
 pub(crate) async fn cancel_task<R: Runtime>(
     app: AppHandle<R>,
     payload: CancelTaskRequest,
 ) -> Result<CancelTaskResponse> {
-    app.schedule_task().cancel_task(payload)
+    app.schedule_task().cancel_task(payload).await
+}
+
+#[command]
+pub(crate) async fn cancel_all<R: Runtime>(
+    app: AppHandle<R>,
+) -> Result<CancelTasksResponse> {
+    app.schedule_task().cancel_all().await
+}
+
+#[command]
+pub(crate) async fn cancel_by_name<R: Runtime>(
+    app: AppHandle<R>,
+    payload: CancelByNameRequest,
+) -> Result<CancelTasksResponse> {
+    app.schedule_task().cancel_by_name(payload).await
 }
 
 #[command]
@@ -34,3 +82,36 @@ pub(crate) async fn list_tasks<R: Runtime>(
 ) -> Result<ListTasksResponse> {
     app.schedule_task().list_tasks()
 }
+
+#[command]
+pub(crate) async fn get_task_history<R: Runtime>(
+    app: AppHandle<R>,
+    payload: GetTaskHistoryRequest,
+) -> Result<GetTaskHistoryResponse> {
+    app.schedule_task().get_task_history(payload)
+}
+
+#[command]
+pub(crate) async fn watch_tasks<R: Runtime>(
+    app: AppHandle<R>,
+    payload: WatchTasksRequest,
+    on_update: Channel<TaskInfo>,
+) -> Result<WatchTasksResponse> {
+    // subscribe before listing, so that no change falls between the two
+    let watch_id = app.state::<TaskWatchers>().add(payload.clone(), on_update.clone());
+    for task in app.schedule_task().list_tasks()?.tasks {
+        if payload.matches(&task) {
+            on_update.send(task)?;
+        }
+    }
+    Ok(WatchTasksResponse { watch_id })
+}
+
+#[command]
+pub(crate) async fn unwatch_tasks<R: Runtime>(
+    app: AppHandle<R>,
+    payload: UnwatchTasksRequest,
+) -> Result<()> {
+    app.state::<TaskWatchers>().remove(payload.watch_id);
+    Ok(())
+}
@@ -1,49 +1,103 @@
 use serde::de::DeserializeOwned;
 use tauri::Manager;
 use tauri::{plugin::PluginApi, AppHandle, Runtime};
-use std::collections::HashMap;
-use std::str::FromStr;
+use std::collections::{HashMap, HashSet};
+use std::hash::{BuildHasher, Hasher, RandomState};
 use std::sync::{Arc, Mutex};
 use uuid::Uuid;
-use chrono::{DateTime, Duration, Local, Utc};
+use chrono::{DateTime, Local, Utc};
 use tokio_cron_scheduler::{Job, JobScheduler, job::JobId};
 
 use crate::models::*;
-use crate::ScheduledTaskHandler;
+use crate::recurrence::{parse_datetime, Recurrence};
+use crate::store::TaskStore;
+use crate::system::{
+  CrontabScheduler, LaunchdScheduler, SystemScheduler, SystemdScheduler, TaskCommand, WindowsTaskScheduler,
+};
+use crate::{events, CancellationToken, TaskContext, TaskHandler};
+
+/// File, under the app data directory, holding the scheduled tasks of the JSON file store.
+#[cfg(feature = "json-store")]
+const TASKS_FILE: &str = "scheduled-tasks.json";
+/// Database, under the app data directory, holding the scheduled tasks of the SQLite store.
+#[cfg(all(feature = "sqlite-store", not(feature = "json-store")))]
+const TASKS_DATABASE: &str = "scheduled-tasks.sqlite";
+/// Most runs made up for at once by [`MisfirePolicy::FireAll`].
+const MAX_CATCH_UP_RUNS: usize = 1000;
 
 pub fn init<R: Runtime, C: DeserializeOwned>(
   app: &AppHandle<R>,
   _api: PluginApi<R, C>,
-  handler: Option<Arc<dyn ScheduledTaskHandler<R> + Send + Sync>>,
+  config: &Config,
+  handler: Option<TaskHandler<R>>,
+  store: Option<Arc<dyn TaskStore>>,
 ) -> crate::Result<ScheduleTask<R>> {
 
 
-  // initialize the job scheduler  
+  // initialize the job scheduler
   let scheduler = tauri::async_runtime::block_on(async { JobScheduler::new().await.unwrap() });
   let scheduler = Arc::new(scheduler);
   let s = scheduler.clone();
   tauri::async_runtime::spawn(async move {
-  //std::thread::spawn(move || {
-      tauri::async_runtime::block_on(async move {
-          s.start().await.unwrap();
-      });
+      s.start().await.unwrap();
   });
   app.manage(scheduler);
 
-  Ok(ScheduleTask {
+  let store = match store {
+    Some(store) => store,
+    None => default_store(app)?,
+  };
+  let schedule_task = ScheduleTask {
     app: app.clone(),
-    scheduled_tasks: Arc::new(Mutex::new(HashMap::new())),
+    store,
+    store_lock: Arc::new(Mutex::new(())),
     job_ids: Arc::new(Mutex::new(HashMap::new())),
+    running: Arc::new(Mutex::new(HashMap::new())),
+    armings: Arc::new(Mutex::new(HashMap::new())),
+    history: config.history.clone(),
+    finished_tasks: config.finished_tasks.clone(),
     handler,
-  })
+    system_scheduler: system_scheduler(app, config),
+  };
+  // a process launched only to run one task exits right after it, leave the timers to the app
+  if crate::check_scheduled_task_args().is_none() {
+    tauri::async_runtime::block_on(schedule_task.restore());
+  }
+  Ok(schedule_task)
 }
 
 /// Access to the schedule-task APIs.
 pub struct ScheduleTask<R: Runtime> {
   app: AppHandle<R>,
-  scheduled_tasks: Arc<Mutex<HashMap<String, TaskInfo>>>,
+  store: Arc<dyn TaskStore>,
+  /// Serializes the read-modify-write updates of tasks in the store.
+  store_lock: Arc<Mutex<()>>,
   job_ids: Arc<Mutex<HashMap<String, JobId>>>,
-  handler: Option<Arc<dyn ScheduledTaskHandler<R> + Send + Sync>>,
+  /// Cancellation tokens of the runs in progress, by task id.
+  running: Arc<Mutex<HashMap<String, CancellationToken>>>,
+  /// Tokens of the timers and cron jobs of the tasks, by task id: once cancelled, they start no more runs.
+  armings: Arc<Mutex<HashMap<String, CancellationToken>>>,
+  history: HistoryRetention,
+  finished_tasks: FinishedTaskRetention,
+  handler: Option<TaskHandler<R>>,
+  system_scheduler: Option<Arc<dyn SystemScheduler>>,
+}
+
+impl<R: Runtime> Clone for ScheduleTask<R> {
+  fn clone(&self) -> Self {
+    Self {
+      app: self.app.clone(),
+      store: self.store.clone(),
+      store_lock: self.store_lock.clone(),
+      job_ids: self.job_ids.clone(),
+      running: self.running.clone(),
+      armings: self.armings.clone(),
+      history: self.history.clone(),
+      finished_tasks: self.finished_tasks.clone(),
+      handler: self.handler.clone(),
+      system_scheduler: self.system_scheduler.clone(),
+    }
+  }
 }
 
 impl<R: Runtime> ScheduleTask<R> {
@@ -54,75 +108,37 @@ impl<R: Runtime> ScheduleTask<R> {
   }
 
   pub async fn schedule_task(&self, payload: ScheduleTaskRequest) -> crate::Result<ScheduleTaskResponse> {
-    dbg!("Scheduling task with todo: {:?}", &payload);
-    let payload = payload.clone();
-    let schedule_time = match payload.schedule_time {
-      ScheduleTime::DateTime(_) => payload.schedule_time,
-      ScheduleTime::Duration(seconds) => {
-        let scheduled_time = Local::now() + Duration::seconds(seconds as i64);
-        ScheduleTime::DateTime(scheduled_time.to_rfc3339())
-      }
-    };
-    // now converts the schedule_time to a Duration
-    let now = Local::now();
-    let duration = match schedule_time.clone() {
-      ScheduleTime::DateTime(dt_str) => {
-        let dt = DateTime::<Utc>::from_str(&dt_str)
-          .map_err(|e| crate::Error::Generic(format!("Invalid datetime format: {}", e)))?;
-        let local_dt = dt.with_timezone(&Local);
-        local_dt.signed_duration_since(now)
-      },
-      ScheduleTime::Duration(seconds) => Duration::seconds(seconds as i64),
-    };
-    // spawns a new task and waits for the duration to elapse
-    let scheduled_tasks = self.scheduled_tasks.clone();
-    let handler = self.handler.clone();
+    if self.handler.as_ref().is_some_and(|handler| !handler.accepts(&payload.task_name)) {
+      return Err(crate::Error::UnknownTask(payload.task_name));
+    }
+    let schedule = Recurrence::normalize(&payload.schedule_time, payload.timezone.as_deref());
+    let recurrence = Recurrence::from_schedule(&schedule, payload.timezone.as_deref())?;
+    let run_at = recurrence.first_run(&Local::now())
+      .ok_or_else(|| crate::Error::Generic(format!("Schedule of task '{}' has no upcoming run", payload.task_name)))?;
+
     let task_id = Uuid::new_v4().to_string();
-    let task_info = TaskInfo {
-      task_id: task_id.clone(),
-      task_name: payload.task_name.clone(),
-      scheduled_time: match &schedule_time {
-        ScheduleTime::DateTime(dt_str) => dt_str.clone(),
-        ScheduleTime::Duration(seconds) => (Local::now() + Duration::seconds(*seconds as i64)).to_rfc3339(),
-      },
-      status: TaskStatus::Scheduled,
-      parameters: payload.parameters.clone(),
-    };
-
-    {
-      let mut tasks = scheduled_tasks.lock().unwrap();
-      tasks.insert(task_id.clone(), task_info);
-    }
-
-    let app = self.app.clone();
-    tokio::spawn({
-      let scheduled_tasks = scheduled_tasks.clone();
-      let handler = handler.clone();
-      let task_id = task_id.clone();
-      let task_name = payload.task_name.clone();
-      let parameters = payload.parameters.clone().unwrap_or_default();
-      async move {
-        tokio::time::sleep(duration.to_std().unwrap()).await;
-        {
-          let mut tasks = scheduled_tasks.lock().unwrap();
-          if let Some(task) = tasks.get_mut(&task_id) {
-            task.status = TaskStatus::Running;
-          }
-        }
-        if let Some(handler) = handler.as_ref() {
-          let handler = handler.clone();
-          let scheduled_tasks = scheduled_tasks.clone();
-          //let app = app.clone();
-          tokio::spawn(async move {
-            let _ = handler.handle_scheduled_task(&task_name, parameters, &app);
-            let mut tasks = scheduled_tasks.lock().unwrap();
-            if let Some(task) = tasks.get_mut(&task_id) {
-              task.status = TaskStatus::Completed;
-            }
-          });
-        }
+    let mut task_info = new_task_info(&task_id, &payload, schedule, &run_at);
+    if let Some(system_scheduler) = self.supporting_system_scheduler(&task_info) {
+      task_info.system_scheduler = Some(system_scheduler.name().to_string());
+      system_scheduler.install(&task_info, &TaskCommand::current_exe(&task_info)?)?;
+      if let Err(e) = self.save_task(&task_info) {
+        let _ = system_scheduler.uninstall(&task_id);
+        return Err(e);
       }
-    });
+      return Ok(ScheduleTaskResponse {
+        task_id,
+        success: true,
+        message: Some(format!("Task scheduled successfully with {}", system_scheduler.name())),
+      });
+    }
+
+    self.save_task(&task_info)?;
+    // a time already past is not a misfire for a new task, it just runs right away
+    if let Err(e) = self.arm_recurrence(&task_id, recurrence, run_at, self.new_arming(&task_id)).await {
+      self.remove_task(&task_id);
+      return Err(e);
+    }
+
     Ok(ScheduleTaskResponse {
       task_id,
       success: true,
@@ -130,24 +146,589 @@ impl<R: Runtime> ScheduleTask<R> {
     })
   }
 
+  /// Schedules a task of kind `K`, see [`crate::TaskKind`].
+  pub async fn schedule<K: crate::TaskKind>(&self, when: ScheduleTime, params: K::Params) -> crate::Result<ScheduleTaskResponse> {
+    self.schedule_task(ScheduleTaskRequest::for_kind::<K>(when, &params)?).await
+  }
+
+  /// Re-arms the tasks loaded from the store that still have runs ahead of them.
+  ///
+  /// Runs missed while the app was closed are handled by the misfire policy of each task.
+  async fn restore(&self) {
+    self.prune_finished_tasks();
+    let pending: Vec<TaskInfo> = match self.store.list() {
+      Ok(tasks) => tasks.into_iter()
+        // tasks installed in an OS scheduler are run by it
+        .filter(|task| task.system_scheduler.is_none())
+        .filter(|task| matches!(task.status, TaskStatus::Scheduled | TaskStatus::Running))
+        .collect(),
+      Err(e) => {
+        eprintln!("[schedule-task] Failed to load scheduled tasks: {}", e);
+        return;
+      }
+    };
+    for task in pending {
+      // a run interrupted by the app closing, or waiting for a retry, is attempted again
+      self.modify_task(&task.task_id, |task| {
+        task.status = TaskStatus::Scheduled;
+        task.next_retry_at = None;
+        // tasks saved before UTC times were kept
+        if let Ok(scheduled_time) = parse_datetime(&task.scheduled_time) {
+          task.set_scheduled_time(&scheduled_time);
+        }
+      });
+      if let Err(e) = self.arm(&task).await {
+        eprintln!("[schedule-task] Failed to restore task {}: {}", task.task_id, e);
+        self.modify_task(&task.task_id, |task| task.status = TaskStatus::Failed);
+      }
+    }
+  }
+
+  /// Starts the timer, or cron job, that runs a task from its `scheduled_time` on.
+  ///
+  /// When that time already passed, the misfire policy of the task is applied first, off the
+  /// caller's thread.
+  async fn arm(&self, task: &TaskInfo) -> crate::Result<()> {
+    let schedule = task.schedule.as_ref()
+      .ok_or_else(|| crate::Error::Generic(format!("Task {} has no schedule", task.task_id)))?;
+    let recurrence = Recurrence::from_schedule(schedule, task.timezone.as_deref())?;
+    let due = parse_datetime(&task.scheduled_time)?;
+    let armed = self.new_arming(&task.task_id);
+    if due > Local::now() {
+      return self.arm_recurrence(&task.task_id, recurrence, due, armed).await;
+    }
+
+    let task = task.clone();
+    let this = self.clone();
+    tauri::async_runtime::spawn(async move {
+      let Some(next_run) = this.catch_up(&task, &recurrence, due, &armed).await else {
+        return;
+      };
+      if let Err(e) = this.arm_recurrence(&task.task_id, recurrence, next_run, armed).await {
+        eprintln!("[schedule-task] Failed to re-arm task {}: {}", task.task_id, e);
+        this.modify_task(&task.task_id, |task| task.status = TaskStatus::Failed);
+      }
+    });
+    Ok(())
+  }
 
-  pub fn cancel_task(&self, payload: CancelTaskRequest) -> crate::Result<CancelTaskResponse> {
-    let mut job_ids = self.job_ids.lock().unwrap();
-    let result = if let Some(job_id) = job_ids.remove(&payload.task_id) {
-      tauri::async_runtime::block_on(async {
-        self.app.state::<Arc<JobScheduler>>()
-          .remove(&job_id)
-          .await
+  /// Runs the runs of a task missed since `due` as its misfire policy says, and moves the
+  /// task to its next run.
+  ///
+  /// Returns that next run, or `None` when the task has no runs left or was cancelled.
+  async fn catch_up(
+    &self,
+    task: &TaskInfo,
+    recurrence: &Recurrence,
+    due: DateTime<Local>,
+    armed: &CancellationToken,
+  ) -> Option<DateTime<Local>> {
+    let now = Local::now();
+    let missed = missed_runs(recurrence, due, &now, task.run_count);
+    let fired = runs_to_fire(task.misfire_policy, &missed, &now);
+    let mut runs = task.run_count;
+    for _ in 0..fired {
+      runs = self.run_task(&task.task_id, RunTrigger::CatchUp, armed).await?;
+    }
+
+    let next_run = recurrence.next_after(&now, runs);
+    self.modify_task(&task.task_id, |task| {
+      if armed.is_cancelled() || matches!(task.status, TaskStatus::Cancelled) {
+        return None;
+      }
+      settle_catch_up(task, next_run, fired == 0 && !missed.is_empty());
+      next_run
+    })?
+  }
+
+  /// Starts the timer, or cron job, of a task whose next run is `first_run`, until `armed` is cancelled.
+  ///
+  /// Cron tasks with a time zone are timed like the others rather than by the `JobScheduler`, so
+  /// that their runs follow the DST rules of [`Recurrence`].
+  async fn arm_recurrence(
+    &self,
+    task_id: &str,
+    recurrence: Recurrence,
+    first_run: DateTime<Local>,
+    armed: CancellationToken,
+  ) -> crate::Result<()> {
+    if let Recurrence::Cron { schedule, timezone: None, .. } = &recurrence {
+      let schedule = schedule.clone();
+      return self.add_cron_job(task_id, &schedule, recurrence, first_run, armed).await;
+    }
+
+    let task_id = task_id.to_string();
+    let this = self.clone();
+    tauri::async_runtime::spawn(async move {
+      let mut run_at = Some(first_run);
+      while let Some(at) = run_at {
+        if armed.run_until_cancelled(tokio::time::sleep(duration_until(&at))).await.is_none() {
+          break;
+        }
+        let Some(runs) = this.run_task(&task_id, RunTrigger::Schedule, &armed).await else {
+          break;
+        };
+        run_at = recurrence.next_after(&at.max(Local::now()), runs);
+        this.finish_run(&task_id, run_at);
+      }
+    });
+    Ok(())
+  }
+
+  /// Registers a recurring task in the `JobScheduler`, keeping it listed with its next fire time.
+  ///
+  /// Occurrences before `first_run` are skipped.
+  async fn add_cron_job(
+    &self,
+    task_id: &str,
+    schedule: &str,
+    recurrence: Recurrence,
+    first_run: DateTime<Local>,
+    armed: CancellationToken,
+  ) -> crate::Result<()> {
+    self.modify_task(task_id, |task| task.set_scheduled_time(&first_run));
+
+    let this = self.clone();
+    let id = task_id.to_string();
+    let job = Job::new_async_tz(schedule, Local, move |_job_id, _scheduler| {
+      let this = this.clone();
+      let id = id.clone();
+      let recurrence = recurrence.clone();
+      let armed = armed.clone();
+      Box::pin(async move {
+        // the job may fire a little early, so only occurrences a full second early are skipped
+        if Local::now() < first_run - chrono::Duration::seconds(1) {
+          return;
+        }
+        if let Some(runs) = this.run_task(&id, RunTrigger::Schedule, &armed).await {
+          this.finish_run(&id, recurrence.next_after(&Local::now(), runs));
+        }
       })
+    })
+    .map_err(|e| crate::Error::Generic(format!("Invalid cron expression '{}': {}", schedule, e)))?;
+
+    let job_id = self.app.state::<Arc<JobScheduler>>().add(job).await
+      .map_err(|e| crate::Error::Generic(format!("Failed to schedule cron job: {}", e)))?;
+    self.job_ids.lock().unwrap().insert(task_id.to_string(), job_id);
+    Ok(())
+  }
+
+  /// Runs the handler for a task, retrying it as its retry policy allows, and records how the run
+  /// ended.
+  ///
+  /// Returns how many times the task has run, or `None` when it was cancelled or `armed` got cancelled.
+  async fn run_task(&self, task_id: &str, trigger: RunTrigger, armed: &CancellationToken) -> Option<u32> {
+    let mut attempt = 1;
+    loop {
+      let (runs, retry_at) = self.run_attempt(task_id, trigger, attempt, armed).await?;
+      let Some(retry_at) = retry_at else {
+        return Some(runs);
+      };
+      armed.run_until_cancelled(tokio::time::sleep(duration_until(&retry_at))).await?;
+      attempt += 1;
+    }
+  }
+
+  /// Runs a task in the process an OS scheduler launched for it, through the same run, retry and
+  /// record steps as the runs timed by the app, then moves the task to its next run.
+  ///
+  /// The process waits for the retries of a failed run. Tasks missing from the store, or
+  /// installed without their id, only get their handler called.
+  pub(crate) async fn run_installed_task(&self, task_id: Option<String>, task_name: String, parameters: TaskParameters) -> crate::Result<()> {
+    let task = match &task_id {
+      Some(task_id) => self.store.get(task_id)?,
+      None => None,
+    };
+    let Some(task) = task else {
+      let Some(handler) = &self.handler else {
+        return Ok(());
+      };
+      let context = TaskContext::new(task_id.unwrap_or_default(), task_name);
+      return handler.handle(&context, parameters, &self.app).await;
+    };
+    let schedule = task.schedule.as_ref()
+      .ok_or_else(|| crate::Error::Generic(format!("Task {} has no schedule", task.task_id)))?;
+    let recurrence = Recurrence::from_schedule(schedule, task.timezone.as_deref())?;
+    let Some(runs) = self.run_task(&task.task_id, RunTrigger::Schedule, &CancellationToken::new()).await else {
+      return Ok(());
+    };
+    self.finish_run(&task.task_id, recurrence.next_after(&Local::now(), runs));
+    match self.store.get(&task.task_id)?.and_then(|task| task.error) {
+      Some(error) => Err(crate::Error::Generic(error)),
+      None => Ok(()),
+    }
+  }
+
+  /// Calls the handler once for a run of a task, unless the task has been cancelled, or disarmed,
+  /// in the meantime. An occurrence due while the task is still running is skipped.
+  ///
+  /// Returns how many times the task has run, and when the attempt is retried if it failed.
+  async fn run_attempt(
+    &self,
+    task_id: &str,
+    trigger: RunTrigger,
+    attempt: u32,
+    armed: &CancellationToken,
+  ) -> Option<(u32, Option<DateTime<Local>>)> {
+    let start = self.modify_task(task_id, |task| {
+      // checked under the store lock, so that a task disarmed by an update never starts its old run
+      if armed.is_cancelled() || matches!(task.status, TaskStatus::Cancelled) {
+        return Err(None);
+      }
+      // a new occurrence waits for the retries of the previous one, and never overlaps a run in progress
+      if (attempt == 1 && task.next_retry_at.is_some()) || matches!(task.status, TaskStatus::Running) {
+        return Err(Some(task.run_count));
+      }
+      task.status = TaskStatus::Running;
+      task.catch_up = trigger == RunTrigger::CatchUp;
+      task.attempt = attempt;
+      task.next_retry_at = None;
+      // runs triggered by hand leave the schedule, and the runs it has left, alone
+      if attempt == 1 && trigger != RunTrigger::Manual {
+        task.run_count += 1;
+        task.remaining_runs = task.remaining_runs.map(|remaining| remaining.saturating_sub(1));
+      }
+      let retry_at = task.retry_policy.as_ref()
+        .filter(|policy| attempt < policy.max_attempts)
+        .map(|policy| Local::now() + retry_delay(policy, attempt));
+      let context = TaskContext {
+        task_id: task.task_id.clone(),
+        task_name: task.task_name.clone(),
+        attempt,
+        next_retry_at: retry_at.map(|at| at.to_rfc3339()),
+        cancellation: CancellationToken::new(),
+      };
+      self.running.lock().unwrap().insert(task_id.to_string(), context.cancellation.clone());
+      Ok((context, task.parameters.clone().unwrap_or_default(), task.run_count, retry_at, task.timeout))
+    })?;
+    let (context, parameters, runs, retry_at, timeout) = match start {
+      Ok(start) => start,
+      // a skipped occurrence leaves the timer armed for the next one
+      Err(runs) => return runs.map(|runs| (runs, None)),
+    };
+    let started_at = Local::now();
+    let result = match self.handler.as_ref() {
+      Some(handler) => {
+        let run = handler.handle(&context, parameters, &self.app);
+        match timeout {
+          // the handler runs on its own task, so it is only told to stop through the token
+          Some(seconds) => tokio::time::timeout(std::time::Duration::from_secs(seconds), run)
+            .await
+            .unwrap_or_else(|_| {
+              context.cancellation.cancel();
+              Err(crate::Error::TimedOut(seconds))
+            }),
+          None => run.await,
+        }
+      }
+      None => Ok(()),
+    };
+    if let Err(e) = &result {
+      eprintln!("[schedule-task] Task {} failed on attempt {}: {}", task_id, attempt, e);
+    }
+    let finished_at = Local::now();
+    let retry_at = retry_at.filter(|_| result.is_err());
+    // the run stops counting as in progress together with its outcome, under the store lock
+    let ended = self.modify_task(task_id, |task| {
+      self.running.lock().unwrap().remove(task_id);
+      let outcome = match &result {
+        _ if matches!(task.status, TaskStatus::Cancelled) => RunOutcome::Cancelled,
+        Ok(()) => RunOutcome::Succeeded,
+        Err(crate::Error::TimedOut(_)) => RunOutcome::TimedOut,
+        Err(_) => RunOutcome::Failed,
+      };
+      task.error = result.as_ref().err().map(|e| e.to_string());
+      task.finished_at = Some(finished_at.to_rfc3339());
+      // a task cancelled during the run is not retried
+      let retry_at = retry_at.filter(|_| matches!(task.status, TaskStatus::Running));
+      if let Some(retry_at) = retry_at {
+        task.status = TaskStatus::Scheduled;
+        task.set_scheduled_time(&retry_at);
+        task.next_retry_at = Some(retry_at.to_rfc3339());
+      }
+      (outcome, retry_at)
+    });
+    let Some((outcome, retry_at)) = ended else {
+      self.running.lock().unwrap().remove(task_id);
+      return None;
+    };
+    let trigger = if attempt == 1 { trigger } else { RunTrigger::Retry };
+    self.record_run(TaskRun {
+      task_id: task_id.to_string(),
+      started_at: started_at.to_rfc3339(),
+      finished_at: finished_at.to_rfc3339(),
+      duration_ms: finished_at.signed_duration_since(started_at).num_milliseconds().max(0) as u64,
+      outcome,
+      error: result.err().map(|e| e.to_string()),
+      attempt,
+      trigger,
+    });
+    Some((runs, retry_at))
+  }
+
+  /// Adds a run to the history of its task, then drops the runs beyond the retention limits.
+  fn record_run(&self, run: TaskRun) {
+    let oldest = self.history.max_age.map(|seconds| Utc::now() - chrono::Duration::seconds(seconds.min(i64::MAX as u64) as i64));
+    let recorded = self.store.add_run(&run)
+      .and_then(|_| self.store.prune_runs(&run.task_id, self.history.max_runs, oldest));
+    if let Err(e) = recorded {
+      eprintln!("[schedule-task] Failed to record the run of task {}: {}", run.task_id, e);
+    }
+  }
+
+  pub fn get_task_history(&self, payload: GetTaskHistoryRequest) -> crate::Result<GetTaskHistoryResponse> {
+    let (runs, total) = self.store.runs(&payload.task_id, payload.offset, payload.limit)?;
+    Ok(GetTaskHistoryResponse { runs, total })
+  }
+
+  /// Moves a task out of `Running`, see [`settle`].
+  ///
+  /// An occurrence skipped because another run was in progress only moves the next run; the run in
+  /// progress settles the task when it ends, never back to a run before that one.
+  fn finish_run(&self, task_id: &str, next_run: Option<DateTime<Local>>) {
+    self.modify_task(task_id, |task| {
+      if !matches!(task.status, TaskStatus::Running) {
+        return;
+      }
+      let scheduled = parse_datetime(&task.scheduled_time).ok();
+      let next_run = next_run.map(|next_run| scheduled.filter(|scheduled| *scheduled > next_run).unwrap_or(next_run));
+      if self.running.lock().unwrap().contains_key(task_id) {
+        if let Some(next_run) = next_run {
+          task.set_scheduled_time(&next_run);
+        }
+      } else {
+        settle(task, next_run);
+      }
+    });
+  }
+
+
+  /// Changes the name, parameters or schedule of a task waiting for its next run, keeping its id.
+  ///
+  /// A new schedule re-arms the task from scratch and resets its run count. Running and finished
+  /// tasks, and tasks waiting for a retry, are rejected.
+  pub async fn update_task(&self, payload: UpdateTaskRequest) -> crate::Result<UpdateTaskResponse> {
+    if let Some(task_name) = &payload.task_name {
+      if self.handler.as_ref().is_some_and(|handler| !handler.accepts(task_name)) {
+        return Err(crate::Error::UnknownTask(task_name.clone()));
+      }
+    }
+    let task_id = payload.task_id;
+    // a new schedule is read in the time zone of the task, which updates do not change
+    let timezone = match &payload.schedule_time {
+      Some(_) => self.store.get(&task_id)?.ok_or_else(|| task_not_found(&task_id))?.timezone,
+      None => None,
+    };
+    let schedule = payload.schedule_time.as_ref()
+      .map(|schedule| Recurrence::normalize(schedule, timezone.as_deref()));
+    let recurrence = schedule.as_ref()
+      .map(|schedule| Recurrence::from_schedule(schedule, timezone.as_deref()))
+      .transpose()?;
+    let run_at = match &recurrence {
+      Some(recurrence) => Some(recurrence.first_run(&Local::now())
+        .ok_or_else(|| crate::Error::Generic(format!("New schedule of task {} has no upcoming run", task_id)))?),
+      None => None,
+    };
+
+    let (task, rescheduled) = self.modify_task(&task_id, |task| {
+      match task.status {
+        TaskStatus::Scheduled if task.next_retry_at.is_none() => {}
+        TaskStatus::Scheduled | TaskStatus::Running => {
+          return Err(crate::Error::Generic(format!("Task {} is running", task.task_id)));
+        }
+        TaskStatus::Paused => {
+          return Err(crate::Error::Generic(format!("Task {} is paused, resume it first", task.task_id)));
+        }
+        _ => return Err(crate::Error::Generic(format!("Task {} has already finished", task.task_id))),
+      }
+      if let Some(task_name) = payload.task_name {
+        task.task_name = task_name;
+      }
+      if let Some(parameters) = payload.parameters {
+        task.parameters = Some(parameters);
+      }
+      let rescheduled = schedule.is_some();
+      if let (Some(schedule), Some(run_at)) = (schedule, run_at) {
+        // under the store lock, so the old timer can no longer start a run
+        self.disarm(&task.task_id);
+        task.remaining_runs = remaining_runs(&schedule);
+        task.run_count = 0;
+        task.schedule = Some(schedule);
+        task.set_scheduled_time(&run_at);
+      }
+      Ok((task.clone(), rescheduled))
+    })
+    .ok_or_else(|| task_not_found(&task_id))??;
+
+    // an OS scheduler runs the task with the name and parameters it was installed with
+    if !rescheduled && task.system_scheduler.is_none() {
+      events::emit_status(&self.app, &task);
+      return Ok(UpdateTaskResponse { task });
+    }
+    if task.system_scheduler.is_some() {
+      self.uninstall_system_task(&task_id)?;
     } else {
-      Ok(())
+      self.remove_cron_job(&task_id).await?;
+    }
+    let recurrence = match recurrence {
+      Some(recurrence) => recurrence,
+      None => Recurrence::from_schedule(task.schedule.as_ref()
+        .ok_or_else(|| crate::Error::Generic(format!("Task {} has no schedule", task_id)))?, task.timezone.as_deref())?,
     };
+    let system_scheduler = self.supporting_system_scheduler(&task).cloned();
+    let armed = match &system_scheduler {
+      Some(system_scheduler) => TaskCommand::current_exe(&task).and_then(|command| system_scheduler.install(&task, &command)),
+      None => match parse_datetime(&task.scheduled_time) {
+        Ok(run_at) => self.arm_recurrence(&task_id, recurrence, run_at, self.new_arming(&task_id)).await,
+        Err(e) => Err(e),
+      },
+    };
+    let task = self.finish_rearm(&task_id, system_scheduler.as_ref(), armed)?;
+    events::emit_status(&self.app, &task);
+    Ok(UpdateTaskResponse { task })
+  }
 
-    {
-      let mut tasks = self.scheduled_tasks.lock().unwrap();
-      if let Some(task) = tasks.get_mut(&payload.task_id) {
-        task.status = TaskStatus::Cancelled;
+  /// Stops a task waiting for its next run from running until it is resumed.
+  pub async fn pause_task(&self, payload: PauseTaskRequest) -> crate::Result<UpdateTaskResponse> {
+    let task = self.modify_task(&payload.task_id, |task| {
+      if task.status != TaskStatus::Scheduled || task.next_retry_at.is_some() {
+        return Err(crate::Error::Generic(format!("Task {} is not waiting for its next run", task.task_id)));
       }
+      self.disarm(&task.task_id);
+      task.status = TaskStatus::Paused;
+      Ok(task.clone())
+    })
+    .ok_or_else(|| task_not_found(&payload.task_id))??;
+    if task.system_scheduler.is_some() {
+      self.uninstall_system_task(&task.task_id)?;
+    } else {
+      self.remove_cron_job(&task.task_id).await?;
+    }
+    Ok(UpdateTaskResponse { task })
+  }
+
+  /// Re-arms a paused task. Runs missed while it was paused are handled by its misfire policy,
+  /// unless it goes back to an OS scheduler.
+  pub async fn resume_task(&self, payload: ResumeTaskRequest) -> crate::Result<UpdateTaskResponse> {
+    let task = self.modify_task(&payload.task_id, |task| {
+      if task.status != TaskStatus::Paused {
+        return Err(crate::Error::Generic(format!("Task {} is not paused", task.task_id)));
+      }
+      task.status = TaskStatus::Scheduled;
+      Ok(task.clone())
+    })
+    .ok_or_else(|| task_not_found(&payload.task_id))??;
+    let system_scheduler = self.supporting_system_scheduler(&task)
+      .filter(|_| task.system_scheduler.is_some())
+      .cloned();
+    let armed = match &system_scheduler {
+      Some(system_scheduler) => TaskCommand::current_exe(&task).and_then(|command| system_scheduler.install(&task, &command)),
+      None => self.arm(&task).await,
+    };
+    let task = self.finish_rearm(&task.task_id, system_scheduler.as_ref(), armed)?;
+    Ok(UpdateTaskResponse { task })
+  }
+
+  /// Runs a task waiting for its next run right away, down the same path as its scheduled runs:
+  /// status, events, history and retries. The run goes on in the background.
+  ///
+  /// With `skip_next`, the next scheduled run is skipped. Runs triggered this way do not count in
+  /// `run_count`, and neither does the skipped run: the task still runs `maxRuns` times on
+  /// schedule. The occurrence of a recurrence rule it skips uses up its `COUNT`, as a missed one does.
+  pub async fn run_now(&self, payload: RunTaskNowRequest) -> crate::Result<RunTaskNowResponse> {
+    let task_id = payload.task_id;
+    let task = self.store.get(&task_id)?.ok_or_else(|| task_not_found(&task_id))?;
+    if task.status != TaskStatus::Scheduled || task.next_retry_at.is_some() {
+      return Err(crate::Error::Generic(format!("Task {} is not waiting for its next run", task_id)));
+    }
+    let mut next_run = Some(parse_datetime(&task.scheduled_time)?);
+    if payload.skip_next {
+      if let Some(system_scheduler) = &task.system_scheduler {
+        return Err(crate::Error::Generic(format!("Task {} is run by {}, its runs cannot be skipped", task_id, system_scheduler)));
+      }
+      let recurrence = Recurrence::from_schedule(task.schedule.as_ref()
+        .ok_or_else(|| crate::Error::Generic(format!("Task {} has no schedule", task_id)))?, task.timezone.as_deref())?;
+      next_run = next_run.and_then(|next_run| recurrence.next_after(&next_run, task.run_count));
+      self.remove_cron_job(&task_id).await?;
+      match next_run {
+        Some(next_run) => self.arm_recurrence(&task_id, recurrence, next_run, self.new_arming(&task_id)).await?,
+        None => self.disarm(&task_id),
+      }
+    }
+
+    // tasks left to an OS scheduler, or without runs left, have no arming to follow
+    let armed = self.armings.lock().unwrap().get(&task_id).cloned().unwrap_or_default();
+    let this = self.clone();
+    let id = task_id.clone();
+    tauri::async_runtime::spawn(async move {
+      if this.run_task(&id, RunTrigger::Manual, &armed).await.is_some() {
+        this.finish_run(&id, next_run);
+      }
+    });
+    let task = self.store.get(&task_id)?.ok_or_else(|| task_not_found(&task_id))?;
+    Ok(RunTaskNowResponse { task })
+  }
+
+  /// Records where a re-armed task now runs, or the error that left it `Failed`.
+  fn finish_rearm(
+    &self,
+    task_id: &str,
+    system_scheduler: Option<&Arc<dyn SystemScheduler>>,
+    armed: crate::Result<()>,
+  ) -> crate::Result<TaskInfo> {
+    let task = self.modify_task(task_id, |task| {
+      task.system_scheduler = system_scheduler.map(|system_scheduler| system_scheduler.name().to_string());
+      if let Err(e) = &armed {
+        task.status = TaskStatus::Failed;
+        task.error = Some(e.to_string());
+      }
+      task.clone()
+    })
+    .ok_or_else(|| task_not_found(task_id))?;
+    armed?;
+    Ok(task)
+  }
+
+  /// Cancels a task so that it never runs again, signalling the token of a run in progress.
+  ///
+  /// Unknown and finished tasks are reported with `success: false`.
+  pub async fn cancel_task(&self, payload: CancelTaskRequest) -> crate::Result<CancelTaskResponse> {
+    let task_id = &payload.task_id;
+    // disarmed under the store lock, so that no run starts once the task is cancelled
+    let previous = self.modify_task(task_id, |task| {
+      if task.status.is_finished() {
+        return None;
+      }
+      self.disarm(task_id);
+      Some(std::mem::replace(&mut task.status, TaskStatus::Cancelled))
+    });
+    let result = match previous {
+      Some(None) => {
+        return Ok(CancelTaskResponse {
+          success: false,
+          message: Some(format!("Task {} has already finished", task_id)),
+        });
+      }
+      // the timers of a paused task are already stopped
+      Some(Some(TaskStatus::Paused)) => Ok(()),
+      Some(Some(_)) => match self.remove_cron_job(task_id).await {
+        Ok(true) => Ok(()),
+        Ok(false) => self.uninstall_system_task(task_id),
+        Err(e) => Err(e),
+      },
+      // entries only listed by the OS scheduler can be cancelled too
+      None if self.installed_in_system_scheduler(task_id) => self.uninstall_system_task(task_id),
+      None => {
+        return Ok(CancelTaskResponse {
+          success: false,
+          message: Some(format!("Task {} not found", task_id)),
+        });
+      }
+    }
+    .map_err(|e| e.to_string());
+
+    if let Some(cancellation) = self.running.lock().unwrap().remove(task_id) {
+      cancellation.cancel();
     }
 
     match result {
@@ -162,12 +743,743 @@ impl<R: Runtime> ScheduleTask<R> {
     }
   }
 
+  /// Cancels every task that has not finished yet.
+  pub async fn cancel_all(&self) -> crate::Result<CancelTasksResponse> {
+    self.cancel_matching(|_| true).await
+  }
+
+  /// Cancels every task with the given name that has not finished yet.
+  pub async fn cancel_by_name(&self, payload: CancelByNameRequest) -> crate::Result<CancelTasksResponse> {
+    self.cancel_matching(|task| task.task_name == payload.task_name).await
+  }
+
+  async fn cancel_matching(&self, matches: impl Fn(&TaskInfo) -> bool) -> crate::Result<CancelTasksResponse> {
+    let mut response = CancelTasksResponse::default();
+    for task in self.list_tasks()?.tasks.into_iter().filter(|task| !task.status.is_finished() && matches(task)) {
+      let cancelled = self.cancel_task(CancelTaskRequest { task_id: task.task_id.clone() }).await?;
+      response.record(task.task_id, cancelled);
+    }
+    Ok(response)
+  }
+
   pub fn list_tasks(&self) -> crate::Result<ListTasksResponse> {
-    let tasks = self.scheduled_tasks.lock().unwrap();
-    let task_list: Vec<TaskInfo> = tasks.values().cloned().collect();
+    let mut tasks = self.store.list()?;
+    // list entries installed by the app that are missing from the store, e.g. after it was reset
+    if let Some(system_scheduler) = &self.system_scheduler {
+      match system_scheduler.list() {
+        Ok(installed) => {
+          let known: HashSet<String> = tasks.iter().map(|task| task.task_id.clone()).collect();
+          tasks.extend(installed.into_iter().filter(|task| !known.contains(&task.task_id)));
+        }
+        Err(e) => eprintln!("[schedule-task] Failed to list tasks of {}: {}", system_scheduler.name(), e),
+      }
+    }
+    for task in tasks.iter_mut().filter(|task| task.scheduled_time_utc.is_empty()) {
+      if let Ok(scheduled_time) = parse_datetime(&task.scheduled_time) {
+        task.set_scheduled_time(&scheduled_time);
+      }
+    }
     Ok(ListTasksResponse {
-      tasks: task_list,
+      tasks,
     })
   }
+
+  /// Returns the token of a new arming of a task, disarming the previous one.
+  fn new_arming(&self, task_id: &str) -> CancellationToken {
+    let armed = CancellationToken::new();
+    if let Some(previous) = self.armings.lock().unwrap().insert(task_id.to_string(), armed.clone()) {
+      previous.cancel();
+    }
+    armed
+  }
+
+  /// Stops the timer, or cron job, of a task from starting runs. A run in progress goes on.
+  fn disarm(&self, task_id: &str) {
+    if let Some(armed) = self.armings.lock().unwrap().remove(task_id) {
+      armed.cancel();
+    }
+  }
+
+  /// Removes the cron job of a task from the `JobScheduler`, returning whether it had one.
+  async fn remove_cron_job(&self, task_id: &str) -> crate::Result<bool> {
+    let Some(job_id) = self.job_ids.lock().unwrap().remove(task_id) else {
+      return Ok(false);
+    };
+    let scheduler = self.app.state::<Arc<JobScheduler>>().inner().clone();
+    scheduler.remove(&job_id).await
+      .map_err(|e| crate::Error::Generic(e.to_string()))?;
+    Ok(true)
+  }
+
+  /// The configured OS scheduler, when it can run `task`.
+  ///
+  /// OS schedulers match cron expressions and rules on the system's local time, so those of tasks
+  /// with a time zone are left to the app.
+  fn supporting_system_scheduler(&self, task: &TaskInfo) -> Option<&Arc<dyn SystemScheduler>> {
+    let schedule = task.schedule.as_ref()?;
+    if task.timezone.is_some() && matches!(schedule, ScheduleTime::Cron(_) | ScheduleTime::Rrule(_)) {
+      return None;
+    }
+    self.system_scheduler.as_ref().filter(|system_scheduler| system_scheduler.supports(schedule))
+  }
+
+  /// Whether the OS scheduler lists a task installed by the app.
+  fn installed_in_system_scheduler(&self, task_id: &str) -> bool {
+    self.system_scheduler.as_ref()
+      .and_then(|system_scheduler| system_scheduler.list().ok())
+      .is_some_and(|tasks| tasks.iter().any(|task| task.task_id == task_id))
+  }
+
+  /// Removes a task from the OS scheduler, if it was installed there.
+  fn uninstall_system_task(&self, task_id: &str) -> crate::Result<()> {
+    let Some(system_scheduler) = &self.system_scheduler else {
+      return Ok(());
+    };
+    let installed = match self.store.get(task_id)? {
+      Some(task) => task.system_scheduler.as_deref() == Some(system_scheduler.name()),
+      // a task only listed by the OS scheduler
+      None => true,
+    };
+    if installed {
+      system_scheduler.uninstall(task_id)?;
+    }
+    Ok(())
+  }
+
+  /// Applies `update` to a stored task, emitting a status event when its status changed.
+  ///
+  /// The task is read, updated and saved in one go by the store, see [`TaskStore::update`].
+  fn modify_task<T>(&self, task_id: &str, update: impl FnOnce(&mut TaskInfo) -> T) -> Option<T> {
+    let lock = self.store_lock.lock().unwrap();
+    let mut update = Some(update);
+    let mut updated = None;
+    let saved = self.store.update(task_id, &mut |task| {
+      if let Some(update) = update.take() {
+        let status = task.status.clone();
+        let result = update(task);
+        updated = Some((status, result, task.clone()));
+      }
+    });
+    drop(lock);
+    if let Err(e) = saved {
+      eprintln!("[schedule-task] Failed to update task {}: {}", task_id, e);
+    }
+    let (status, result, task) = updated?;
+    if task.status != status {
+      events::emit_status(&self.app, &task);
+      if task.status.is_finished() {
+        self.prune_finished_tasks();
+      }
+    }
+    Some(result)
+  }
+
+  /// Removes the finished tasks beyond the retention limits, if any are set, with their history
+  /// and any entry left in the OS scheduler.
+  fn prune_finished_tasks(&self) {
+    if self.finished_tasks == FinishedTaskRetention::default() {
+      return;
+    }
+    let expired = {
+      let _lock = self.store_lock.lock().unwrap();
+      let expired = match self.store.list_finished() {
+        Ok(tasks) => expired_tasks(tasks, &self.finished_tasks, &Utc::now()),
+        Err(e) => {
+          eprintln!("[schedule-task] Failed to load finished tasks: {}", e);
+          return;
+        }
+      };
+      for task in &expired {
+        let removed = self.store.remove(&task.task_id)
+          .and_then(|_| self.store.prune_runs(&task.task_id, Some(0), None));
+        if let Err(e) = removed {
+          eprintln!("[schedule-task] Failed to remove finished task {}: {}", task.task_id, e);
+        }
+      }
+      expired
+    };
+    for task in expired.iter().filter(|task| task.system_scheduler.is_some()) {
+      if let Err(e) = self.uninstall_system_task(&task.task_id) {
+        eprintln!("[schedule-task] Failed to uninstall finished task {}: {}", task.task_id, e);
+      }
+    }
+  }
+
+  /// Stores a new task and emits its status event.
+  fn save_task(&self, task: &TaskInfo) -> crate::Result<()> {
+    let _lock = self.store_lock.lock().unwrap();
+    self.store.save(task)?;
+    events::emit_status(&self.app, task);
+    Ok(())
+  }
+
+  fn remove_task(&self, task_id: &str) {
+    let _lock = self.store_lock.lock().unwrap();
+    if let Err(e) = self.store.remove(task_id) {
+      eprintln!("[schedule-task] Failed to remove task {}: {}", task_id, e);
+    }
+  }
+}
+
+fn new_task_info(task_id: &str, payload: &ScheduleTaskRequest, schedule: ScheduleTime, run_at: &DateTime<Local>) -> TaskInfo {
+  let remaining_runs = remaining_runs(&schedule);
+  let mut task = TaskInfo {
+    task_id: task_id.to_string(),
+    task_name: payload.task_name.clone(),
+    scheduled_time: String::new(),
+    scheduled_time_utc: String::new(),
+    status: TaskStatus::Scheduled,
+    parameters: payload.parameters.clone(),
+    run_count: 0,
+    remaining_runs,
+    schedule: Some(schedule),
+    misfire_policy: payload.misfire_policy.unwrap_or_default(),
+    catch_up: false,
+    system_scheduler: None,
+    error: None,
+    finished_at: None,
+    retry_policy: payload.retry_policy.clone(),
+    attempt: 0,
+    next_retry_at: None,
+    timeout: payload.timeout,
+    tags: payload.tags.clone(),
+    timezone: payload.timezone.clone(),
+  };
+  task.set_scheduled_time(run_at);
+  task
+}
+
+fn task_not_found(task_id: &str) -> crate::Error {
+  crate::Error::Generic(format!("Task {} not found", task_id))
 }
 
+/// The finished tasks among `tasks` beyond the retention limits: the oldest ones past
+/// `max_tasks`, and those finished longer than `max_age` ago.
+///
+/// Tasks are dated by their last run, or by their scheduled time when they never ran.
+fn expired_tasks(tasks: Vec<TaskInfo>, retention: &FinishedTaskRetention, now: &DateTime<Utc>) -> Vec<TaskInfo> {
+  let finished_at = |task: &TaskInfo| {
+    let scheduled_time = if task.scheduled_time_utc.is_empty() { &task.scheduled_time } else { &task.scheduled_time_utc };
+    parse_datetime(task.finished_at.as_deref().unwrap_or(scheduled_time)).ok()
+  };
+  let mut finished: Vec<(Option<DateTime<Local>>, TaskInfo)> = tasks.into_iter()
+    .filter(|task| task.status.is_finished())
+    .map(|task| (finished_at(&task), task))
+    .collect();
+  // most recently finished first
+  finished.sort_by_key(|(finished_at, _)| std::cmp::Reverse(*finished_at));
+  let oldest = retention.max_age.map(|seconds| *now - chrono::Duration::seconds(seconds.min(i64::MAX as u64) as i64));
+  finished.into_iter()
+    .enumerate()
+    .filter(|(index, (finished_at, _))| {
+      retention.max_tasks.is_some_and(|max_tasks| *index >= max_tasks)
+        || oldest.zip(*finished_at).is_some_and(|(oldest, finished_at)| finished_at < oldest)
+    })
+    .map(|(_, (_, task))| task)
+    .collect()
+}
+
+/// Runs of a new task limited by its schedule, if any.
+fn remaining_runs(schedule: &ScheduleTime) -> Option<u32> {
+  match schedule {
+    ScheduleTime::Interval(interval) => interval.max_runs,
+    _ => None,
+  }
+}
+
+/// Moves a task to its next run, or ends it: `Failed` when its last run failed, `Completed` otherwise.
+fn settle(task: &mut TaskInfo, next_run: Option<DateTime<Local>>) {
+  match next_run {
+    Some(next_run) => {
+      task.status = TaskStatus::Scheduled;
+      task.set_scheduled_time(&next_run);
+    }
+    None if task.error.is_some() => task.status = TaskStatus::Failed,
+    None => task.status = TaskStatus::Completed,
+  }
+}
+
+/// Moves a task to its next run after its missed runs were handled, see [`settle`].
+///
+/// A task with no run left whose last run was `skipped` ends `Skipped`, whatever its earlier runs did.
+fn settle_catch_up(task: &mut TaskInfo, next_run: Option<DateTime<Local>>, skipped: bool) {
+  match next_run {
+    None if skipped => task.status = TaskStatus::Skipped,
+    next_run => settle(task, next_run),
+  }
+}
+
+/// The runs of a task due from `due` up to `now`, at most [`MAX_CATCH_UP_RUNS`] of them.
+fn missed_runs(recurrence: &Recurrence, due: DateTime<Local>, now: &DateTime<Local>, runs: u32) -> Vec<DateTime<Local>> {
+  let mut missed = Vec::new();
+  let mut next = Some(due);
+  while let Some(at) = next.filter(|at| at <= now) {
+    missed.push(at);
+    if missed.len() >= MAX_CATCH_UP_RUNS {
+      break;
+    }
+    next = recurrence.next_after(&at, runs + missed.len() as u32);
+  }
+  missed
+}
+
+/// How many of the `missed` runs a task with the given policy makes up for.
+fn runs_to_fire(policy: MisfirePolicy, missed: &[DateTime<Local>], now: &DateTime<Local>) -> usize {
+  match (policy, missed.last()) {
+    (_, None) | (MisfirePolicy::Skip, _) => 0,
+    (MisfirePolicy::FireOnce, _) => 1,
+    (MisfirePolicy::FireAll, _) => missed.len(),
+    (MisfirePolicy::FireIfWithin(seconds), Some(last)) => {
+      usize::from(now.signed_duration_since(*last).num_seconds() <= seconds as i64)
+    }
+  }
+}
+
+/// Delay before the attempt following `attempt`, see [`RetryPolicy`].
+fn retry_delay(policy: &RetryPolicy, attempt: u32) -> chrono::Duration {
+  let exponent = attempt.saturating_sub(1).min(i32::MAX as u32) as i32;
+  let delay = (policy.initial_delay as f64 * policy.multiplier.powi(exponent)).min(policy.max_delay as f64);
+  // a hash of a randomly keyed hasher is as random as jitter needs
+  let random = RandomState::new().build_hasher().finish() as f64 / u64::MAX as f64;
+  let jitter = policy.jitter.clamp(0.0, 1.0) * (random * 2.0 - 1.0);
+  let seconds = (delay * (1.0 + jitter)).max(0.0);
+  chrono::Duration::milliseconds(if seconds.is_finite() { (seconds * 1000.0) as i64 } else { 0 })
+}
+
+fn duration_until(run_at: &DateTime<Local>) -> std::time::Duration {
+  run_at.signed_duration_since(Local::now()).to_std().unwrap_or_default()
+}
+
+/// Builds the OS scheduler selected in the plugin configuration.
+fn system_scheduler<R: Runtime>(app: &AppHandle<R>, config: &Config) -> Option<Arc<dyn SystemScheduler>> {
+  let app_id = app.config().identifier.clone();
+  match config.system_scheduler.as_ref()? {
+    SystemSchedulerConfig::Crontab { command } => {
+      let mut crontab = CrontabScheduler::new(app_id);
+      if let Some(command) = command {
+        crontab = crontab.with_command(command);
+      }
+      Some(Arc::new(crontab))
+    }
+    SystemSchedulerConfig::Systemd { unit_dir, systemctl, wake_system } => {
+      let mut systemd = SystemdScheduler::new(app_id).with_wake_system(*wake_system);
+      if let Some(unit_dir) = unit_dir {
+        systemd = systemd.with_unit_dir(unit_dir);
+      }
+      if let Some(systemctl) = systemctl {
+        systemd = systemd.with_systemctl(systemctl);
+      }
+      Some(Arc::new(systemd))
+    }
+    SystemSchedulerConfig::Launchd { agents_dir, launchctl } => {
+      let mut launchd = LaunchdScheduler::new(app_id);
+      if let Some(agents_dir) = agents_dir {
+        launchd = launchd.with_agents_dir(agents_dir);
+      }
+      if let Some(launchctl) = launchctl {
+        launchd = launchd.with_launchctl(launchctl);
+      }
+      Some(Arc::new(launchd))
+    }
+    SystemSchedulerConfig::TaskScheduler { schtasks, wake_to_run } => {
+      let mut task_scheduler = WindowsTaskScheduler::new(app_id).with_wake_to_run(*wake_to_run);
+      if let Some(schtasks) = schtasks {
+        task_scheduler = task_scheduler.with_schtasks(schtasks);
+      }
+      Some(Arc::new(task_scheduler))
+    }
+  }
+}
+
+/// The store used when the app does not provide one: the JSON file store when enabled, then
+/// SQLite, then memory.
+#[allow(unused_variables)]
+fn default_store<R: Runtime>(app: &AppHandle<R>) -> crate::Result<Arc<dyn TaskStore>> {
+  #[cfg(feature = "json-store")]
+  return Ok(Arc::new(crate::store::JsonFileStore::new(app.path().app_data_dir()?.join(TASKS_FILE))?));
+  #[cfg(all(feature = "sqlite-store", not(feature = "json-store")))]
+  return Ok(Arc::new(crate::store::SqliteStore::open(app.path().app_data_dir()?.join(TASKS_DATABASE))?));
+  #[cfg(all(feature = "memory-store", not(any(feature = "json-store", feature = "sqlite-store"))))]
+  return Ok(Arc::new(crate::store::MemoryStore::new()));
+  #[cfg(not(any(feature = "json-store", feature = "sqlite-store", feature = "memory-store")))]
+  Err(crate::Error::Generic(
+    "No task store available: enable a store feature or pass a store to init_with_handler_and_store".to_string(),
+  ))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::recurrence::Interval;
+  use chrono::Duration;
+
+  fn every(every: Duration, first_run: DateTime<Local>, max_runs: Option<u32>) -> Recurrence {
+    Recurrence::Interval {
+      rule: Interval { first_run, every, end_at: None },
+      max_runs,
+    }
+  }
+
+  #[test]
+  fn lists_the_runs_missed_since_due() {
+    let now = Local::now();
+    let due = now - Duration::minutes(35);
+    let recurrence = every(Duration::minutes(10), due, None);
+    let missed = missed_runs(&recurrence, due, &now, 0);
+    assert_eq!(missed, [due, due + Duration::minutes(10), due + Duration::minutes(20), due + Duration::minutes(30)]);
+    assert!(missed_runs(&recurrence, now + Duration::minutes(5), &now, 0).is_empty());
+  }
+
+  #[test]
+  fn stops_missed_runs_at_the_runs_left() {
+    let now = Local::now();
+    let due = now - Duration::minutes(35);
+    let recurrence = every(Duration::minutes(10), due, Some(3));
+    assert_eq!(missed_runs(&recurrence, due, &now, 1), [due, due + Duration::minutes(10)]);
+  }
+
+  #[test]
+  fn caps_missed_runs() {
+    let now = Local::now();
+    let due = now - Duration::hours(2);
+    let missed = missed_runs(&every(Duration::seconds(1), due, None), due, &now, 0);
+    assert_eq!(missed.len(), MAX_CATCH_UP_RUNS);
+    assert_eq!(missed.last(), Some(&(due + Duration::seconds(MAX_CATCH_UP_RUNS as i64 - 1))));
+  }
+
+  #[test]
+  fn fires_missed_runs_as_the_misfire_policy_says() {
+    let now = Local::now();
+    let missed = [now - Duration::minutes(25), now - Duration::minutes(15), now - Duration::minutes(5)];
+    assert_eq!(runs_to_fire(MisfirePolicy::Skip, &missed, &now), 0);
+    assert_eq!(runs_to_fire(MisfirePolicy::FireOnce, &missed, &now), 1);
+    assert_eq!(runs_to_fire(MisfirePolicy::FireAll, &missed, &now), 3);
+    assert_eq!(runs_to_fire(MisfirePolicy::FireIfWithin(600), &missed, &now), 1);
+    assert_eq!(runs_to_fire(MisfirePolicy::FireIfWithin(60), &missed, &now), 0);
+    for policy in [MisfirePolicy::Skip, MisfirePolicy::FireOnce, MisfirePolicy::FireAll, MisfirePolicy::FireIfWithin(600)] {
+      assert_eq!(runs_to_fire(policy, &[], &now), 0);
+    }
+  }
+
+  /// A one-time task due `minutes_ago` minutes ago, handled as a task restored after its run was missed.
+  fn settle_missed_one_time_task(policy: MisfirePolicy, minutes_ago: i64) -> TaskInfo {
+    let now = Local::now();
+    let due = now - Duration::minutes(minutes_ago);
+    let request: ScheduleTaskRequest = serde_json::from_value(serde_json::json!({
+      "taskName": "backup",
+      "scheduleTime": { "dateTime": due.to_rfc3339() },
+    }))
+    .unwrap();
+    let mut task = new_task_info("1", &request, request.schedule_time.clone(), &due);
+    task.misfire_policy = policy;
+    let recurrence = Recurrence::from_schedule(task.schedule.as_ref().unwrap(), None).unwrap();
+    let missed = missed_runs(&recurrence, due, &now, task.run_count);
+    let fired = runs_to_fire(policy, &missed, &now);
+    let runs = task.run_count + fired as u32;
+    settle_catch_up(&mut task, recurrence.next_after(&now, runs), fired == 0 && !missed.is_empty());
+    task
+  }
+
+  #[test]
+  fn ends_one_time_tasks_whose_run_is_skipped_as_skipped() {
+    for policy in [MisfirePolicy::Skip, MisfirePolicy::FireIfWithin(60)] {
+      let task = settle_missed_one_time_task(policy, 30);
+      assert_eq!(task.status, TaskStatus::Skipped);
+      assert_eq!(task.run_count, 0);
+      assert!(task.error.is_none());
+    }
+    assert_eq!(settle_missed_one_time_task(MisfirePolicy::FireIfWithin(3600), 30).status, TaskStatus::Completed);
+  }
+
+  /// A task that ran last `hours_ago` hours ago and ended with `status`.
+  fn finished_task(task_id: &str, status: TaskStatus, hours_ago: i64) -> TaskInfo {
+    let request: ScheduleTaskRequest = serde_json::from_value(serde_json::json!({
+      "taskName": "backup",
+      "scheduleTime": { "duration": 60 },
+    }))
+    .unwrap();
+    let finished_at = Local::now() - Duration::hours(hours_ago);
+    let mut task = new_task_info(task_id, &request, request.schedule_time.clone(), &finished_at);
+    task.status = status;
+    task.finished_at = Some(finished_at.to_rfc3339());
+    task
+  }
+
+  fn expired_ids(tasks: Vec<TaskInfo>, max_tasks: Option<usize>, max_age: Option<u64>) -> Vec<String> {
+    let retention = FinishedTaskRetention { max_tasks, max_age };
+    expired_tasks(tasks, &retention, &Utc::now()).into_iter().map(|task| task.task_id).collect()
+  }
+
+  #[test]
+  fn expires_the_oldest_finished_tasks_past_max_tasks() {
+    let tasks = vec![
+      finished_task("completed-3h", TaskStatus::Completed, 3),
+      finished_task("failed-1h", TaskStatus::Failed, 1),
+      finished_task("scheduled", TaskStatus::Scheduled, 10),
+      finished_task("cancelled-2h", TaskStatus::Cancelled, 2),
+      finished_task("completed-5h", TaskStatus::Completed, 5),
+    ];
+    assert_eq!(expired_ids(tasks.clone(), Some(2), None), ["completed-3h", "completed-5h"]);
+    assert!(expired_ids(tasks.clone(), None, None).is_empty());
+    assert_eq!(expired_ids(tasks, Some(0), None).len(), 4);
+  }
+
+  #[test]
+  fn expires_finished_tasks_past_max_age() {
+    let tasks = vec![
+      finished_task("completed-3h", TaskStatus::Completed, 3),
+      finished_task("paused", TaskStatus::Paused, 10),
+      finished_task("failed-1h", TaskStatus::Failed, 1),
+    ];
+    assert_eq!(expired_ids(tasks.clone(), None, Some(2 * 3600)), ["completed-3h"]);
+    assert_eq!(expired_ids(tasks, Some(1), Some(4 * 3600)), ["completed-3h"]);
+  }
+
+  #[test]
+  fn keeps_finished_tasks_by_default() {
+    let tasks = vec![finished_task("completed", TaskStatus::Completed, 24 * 365), finished_task("failed", TaskStatus::Failed, 1)];
+    assert!(expired_tasks(tasks, &FinishedTaskRetention::default(), &Utc::now()).is_empty());
+  }
+
+  #[test]
+  fn dates_tasks_that_never_ran_by_their_scheduled_time() {
+    let mut cancelled = finished_task("cancelled", TaskStatus::Cancelled, 4);
+    cancelled.finished_at = None;
+    let tasks = vec![cancelled, finished_task("completed-2h", TaskStatus::Completed, 2)];
+    assert_eq!(expired_ids(tasks, Some(1), None), ["cancelled"]);
+  }
+
+  fn policy(jitter: f64) -> RetryPolicy {
+    RetryPolicy {
+      max_attempts: 5,
+      initial_delay: 30,
+      multiplier: 2.0,
+      max_delay: 3600,
+      jitter,
+    }
+  }
+
+  #[test]
+  fn grows_retry_delays_exponentially() {
+    let delays: Vec<i64> = (1..=4).map(|attempt| retry_delay(&policy(0.0), attempt).num_seconds()).collect();
+    assert_eq!(delays, [30, 60, 120, 240]);
+  }
+
+  #[test]
+  fn caps_retry_delays() {
+    assert_eq!(retry_delay(&policy(0.0), 10).num_seconds(), 3600);
+    assert_eq!(retry_delay(&policy(0.0), u32::MAX).num_seconds(), 3600);
+  }
+
+  #[test]
+  fn keeps_jitter_within_bounds() {
+    let delays: Vec<i64> = (0..200).map(|_| retry_delay(&policy(0.1), 1).num_milliseconds()).collect();
+    assert!(delays.iter().all(|delay| (27_000..=33_000).contains(delay)));
+    assert!(delays.iter().any(|delay| *delay != delays[0]));
+    // jitter is at most the whole delay
+    assert!((0..200).all(|_| (0..=60_000).contains(&retry_delay(&policy(5.0), 1).num_milliseconds())));
+  }
+
+  /// The APIs of the plugin over an in-memory store, without a handler or an OS scheduler.
+  #[cfg(feature = "memory-store")]
+  fn schedule_task() -> (tauri::App<tauri::test::MockRuntime>, ScheduleTask<tauri::test::MockRuntime>) {
+    let app = tauri::test::mock_app();
+    app.manage(Arc::new(tauri::async_runtime::block_on(JobScheduler::new()).unwrap()));
+    let schedule_task = ScheduleTask {
+      app: app.handle().clone(),
+      store: Arc::new(crate::store::MemoryStore::new()),
+      store_lock: Default::default(),
+      job_ids: Default::default(),
+      running: Default::default(),
+      armings: Default::default(),
+      history: HistoryRetention::default(),
+      finished_tasks: FinishedTaskRetention::default(),
+      handler: None,
+      system_scheduler: None,
+    };
+    (app, schedule_task)
+  }
+
+  /// Schedules a one-time task due in an hour, returning its id.
+  #[cfg(feature = "memory-store")]
+  fn schedule_in_an_hour<R: Runtime>(schedule_task: &ScheduleTask<R>, task_name: &str) -> String {
+    let request = serde_json::from_value(serde_json::json!({
+      "taskName": task_name,
+      "scheduleTime": { "dateTime": (Local::now() + Duration::hours(1)).to_rfc3339() },
+    }))
+    .unwrap();
+    tauri::async_runtime::block_on(schedule_task.schedule_task(request)).unwrap().task_id
+  }
+
+  #[cfg(feature = "memory-store")]
+  fn is_armed<R: Runtime>(schedule_task: &ScheduleTask<R>, task_id: &str) -> bool {
+    schedule_task.armings.lock().unwrap().get(task_id).is_some_and(|armed| !armed.is_cancelled())
+  }
+
+  #[cfg(feature = "memory-store")]
+  fn update(task_id: &str, task_name: Option<&str>, schedule_time: Option<ScheduleTime>) -> UpdateTaskRequest {
+    UpdateTaskRequest {
+      task_id: task_id.to_string(),
+      task_name: task_name.map(str::to_string),
+      schedule_time,
+      parameters: None,
+    }
+  }
+
+  #[test]
+  #[cfg(feature = "memory-store")]
+  fn rejects_updates_of_running_tasks() {
+    let (_app, schedule_task) = schedule_task();
+    let task_id = schedule_in_an_hour(&schedule_task, "backup");
+    schedule_task.modify_task(&task_id, |task| task.status = TaskStatus::Running);
+
+    let result = tauri::async_runtime::block_on(schedule_task.update_task(update(&task_id, Some("cleanup"), None)));
+    assert!(matches!(result, Err(crate::Error::Generic(message)) if message == format!("Task {} is running", task_id)));
+    let task = schedule_task.store.get(&task_id).unwrap().unwrap();
+    assert_eq!(task.task_name, "backup");
+    assert_eq!(task.status, TaskStatus::Running);
+  }
+
+  #[test]
+  #[cfg(feature = "memory-store")]
+  fn rearms_tasks_given_a_new_schedule() {
+    let (_app, schedule_task) = schedule_task();
+    let task_id = schedule_in_an_hour(&schedule_task, "backup");
+    let armed = schedule_task.armings.lock().unwrap()[&task_id].clone();
+
+    let run_at = Local::now() + Duration::hours(2);
+    let schedule_time = ScheduleTime::DateTime(run_at.to_rfc3339());
+    let task = tauri::async_runtime::block_on(schedule_task.update_task(update(&task_id, Some("cleanup"), Some(schedule_time))))
+      .unwrap()
+      .task;
+    assert_eq!(task.task_name, "cleanup");
+    assert_eq!(task.status, TaskStatus::Scheduled);
+    assert_eq!(parse_datetime(&task.scheduled_time).unwrap().timestamp(), run_at.timestamp());
+    assert!(armed.is_cancelled());
+    assert!(is_armed(&schedule_task, &task_id));
+  }
+
+  #[test]
+  #[cfg(feature = "memory-store")]
+  fn rearms_paused_tasks_on_resume() {
+    let (_app, schedule_task) = schedule_task();
+    let task_id = schedule_in_an_hour(&schedule_task, "backup");
+    let scheduled_time = schedule_task.store.get(&task_id).unwrap().unwrap().scheduled_time;
+    let armed = schedule_task.armings.lock().unwrap()[&task_id].clone();
+
+    let task = tauri::async_runtime::block_on(schedule_task.pause_task(PauseTaskRequest { task_id: task_id.clone() })).unwrap().task;
+    assert_eq!(task.status, TaskStatus::Paused);
+    assert!(armed.is_cancelled());
+    assert!(!is_armed(&schedule_task, &task_id));
+    assert!(tauri::async_runtime::block_on(schedule_task.pause_task(PauseTaskRequest { task_id: task_id.clone() })).is_err());
+    assert!(tauri::async_runtime::block_on(schedule_task.update_task(update(&task_id, Some("cleanup"), None))).is_err());
+
+    let task = tauri::async_runtime::block_on(schedule_task.resume_task(ResumeTaskRequest { task_id: task_id.clone() })).unwrap().task;
+    assert_eq!(task.status, TaskStatus::Scheduled);
+    assert_eq!(task.scheduled_time, scheduled_time);
+    assert!(is_armed(&schedule_task, &task_id));
+    assert!(tauri::async_runtime::block_on(schedule_task.resume_task(ResumeTaskRequest { task_id })).is_err());
+  }
+
+  #[test]
+  #[cfg(feature = "memory-store")]
+  fn reports_unknown_tasks_as_not_cancelled() {
+    let (_app, schedule_task) = schedule_task();
+    let response = tauri::async_runtime::block_on(schedule_task.cancel_task(CancelTaskRequest { task_id: "missing".to_string() })).unwrap();
+    assert!(!response.success);
+    assert_eq!(response.message.as_deref(), Some("Task missing not found"));
+  }
+
+  #[test]
+  #[cfg(feature = "memory-store")]
+  fn cancels_tasks_by_name_then_all_of_them() {
+    let (_app, schedule_task) = schedule_task();
+    let backup = schedule_in_an_hour(&schedule_task, "backup");
+    let cleanup = schedule_in_an_hour(&schedule_task, "cleanup");
+    let status = |task_id: &str| schedule_task.store.get(task_id).unwrap().unwrap().status;
+
+    let response = tauri::async_runtime::block_on(schedule_task.cancel_by_name(CancelByNameRequest { task_name: "backup".to_string() })).unwrap();
+    assert_eq!(response.cancelled, [backup.as_str()]);
+    assert!(response.errors.is_empty());
+    assert_eq!(status(&backup), TaskStatus::Cancelled);
+    assert_eq!(status(&cleanup), TaskStatus::Scheduled);
+    assert!(!is_armed(&schedule_task, &backup));
+    assert!(is_armed(&schedule_task, &cleanup));
+
+    // the task cancelled already is left out
+    let response = tauri::async_runtime::block_on(schedule_task.cancel_all()).unwrap();
+    assert_eq!(response.cancelled, [cleanup.as_str()]);
+    assert_eq!(status(&cleanup), TaskStatus::Cancelled);
+    assert!(!is_armed(&schedule_task, &cleanup));
+
+    let response = tauri::async_runtime::block_on(schedule_task.cancel_task(CancelTaskRequest { task_id: backup.clone() })).unwrap();
+    assert!(!response.success);
+    assert_eq!(response.message, Some(format!("Task {} has already finished", backup)));
+  }
+
+  /// Waits for a task to finish, failing after ten seconds.
+  #[cfg(feature = "memory-store")]
+  fn wait_until_finished<R: Runtime>(schedule_task: &ScheduleTask<R>, task_id: &str) -> TaskInfo {
+    for _ in 0..200 {
+      let task = schedule_task.store.get(task_id).unwrap().unwrap();
+      if task.status.is_finished() {
+        return task;
+      }
+      std::thread::sleep(std::time::Duration::from_millis(50));
+    }
+    panic!("task {} did not finish", task_id);
+  }
+
+  /// Schedules a task, runs it right away skipping its next run, then lets it run to its end.
+  #[cfg(feature = "memory-store")]
+  fn run_now_skipping_next<R: Runtime>(schedule_task: &ScheduleTask<R>, schedule_time: serde_json::Value) -> TaskInfo {
+    let request = serde_json::from_value(serde_json::json!({ "taskName": "backup", "scheduleTime": schedule_time })).unwrap();
+    let task_id = tauri::async_runtime::block_on(schedule_task.schedule_task(request)).unwrap().task_id;
+    let run_now = RunTaskNowRequest { task_id: task_id.clone(), skip_next: true };
+    tauri::async_runtime::block_on(schedule_task.run_now(run_now)).unwrap();
+    wait_until_finished(schedule_task, &task_id)
+  }
+
+  #[test]
+  #[cfg(feature = "memory-store")]
+  fn keeps_skipped_runs_out_of_max_runs() {
+    let (_app, schedule_task) = schedule_task();
+    let start_at = (Local::now() + Duration::seconds(1)).to_rfc3339();
+    let task = run_now_skipping_next(&schedule_task, serde_json::json!({ "interval": { "every": 1, "startAt": start_at, "maxRuns": 1 } }));
+    assert_eq!(task.status, TaskStatus::Completed);
+    assert_eq!(task.run_count, 1);
+    assert_eq!(task.remaining_runs, Some(0));
+    let (runs, _) = schedule_task.store.runs(&task.task_id, 0, None).unwrap();
+    let triggers: Vec<RunTrigger> = runs.into_iter().rev().map(|run| run.trigger).collect();
+    assert_eq!(triggers, [RunTrigger::Manual, RunTrigger::Schedule]);
+  }
+
+  #[test]
+  #[cfg(feature = "memory-store")]
+  fn uses_up_the_rrule_occurrences_skipped() {
+    let (_app, schedule_task) = schedule_task();
+    let start_at = (Local::now() + Duration::seconds(1)).to_rfc3339();
+    let task = run_now_skipping_next(&schedule_task, serde_json::json!({ "rrule": { "rule": "FREQ=SECONDLY;COUNT=3", "startAt": start_at } }));
+    assert_eq!(task.status, TaskStatus::Completed);
+    assert_eq!(task.run_count, 2);
+    assert_eq!(schedule_task.store.runs(&task.task_id, 0, None).unwrap().1, 3);
+  }
+
+  #[test]
+  #[cfg(feature = "memory-store")]
+  fn cancels_cron_tasks_on_a_current_thread_runtime() {
+    let (_app, schedule_task) = schedule_task();
+    let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+    runtime.block_on(async {
+      let request = serde_json::from_value(serde_json::json!({ "taskName": "backup", "scheduleTime": { "cron": "0 9 * * *" } })).unwrap();
+      let task_id = schedule_task.schedule_task(request).await.unwrap().task_id;
+      let job_id = schedule_task.job_ids.lock().unwrap()[&task_id];
+
+      let response = schedule_task.cancel_task(CancelTaskRequest { task_id: task_id.clone() }).await.unwrap();
+      assert!(response.success);
+      assert!(schedule_task.job_ids.lock().unwrap().is_empty());
+      let mut scheduler = JobScheduler::clone(&schedule_task.app.state::<Arc<JobScheduler>>());
+      assert!(scheduler.next_tick_for_job(job_id).await.unwrap().is_none());
+    });
+  }
+}
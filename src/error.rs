@@ -6,9 +6,26 @@ pub type Result<T> = std::result::Result<T, Error>;
 pub enum Error {
   #[error(transparent)]
   Io(#[from] std::io::Error),
+  #[error(transparent)]
+  Json(#[from] serde_json::Error),
+  #[error(transparent)]
+  Tauri(#[from] tauri::Error),
+  #[cfg(feature = "sqlite-store")]
+  #[error(transparent)]
+  Sqlite(#[from] rusqlite::Error),
   #[cfg(mobile)]
   #[error(transparent)]
   PluginInvoke(#[from] tauri::plugin::mobile::PluginInvokeError),
+  #[error("No handler is registered for task '{0}'")]
+  UnknownTask(String),
+  #[error("Task handler panicked: {0}")]
+  HandlerPanicked(String),
+  #[error("Task timed out after {0} seconds")]
+  TimedOut(u64),
+  #[error("Invalid task parameters: {0}")]
+  InvalidParameters(String),
+  #[error("Unknown time zone '{0}'")]
+  UnknownTimezone(String),
   #[error("Tauri Schedule Task error: {0}")] 
   Generic(String)
 }
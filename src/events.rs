@@ -0,0 +1,75 @@
+//! Events emitted on every status change of a task, with its [`TaskInfo`] as payload.
+//!
+//! The same changes are streamed to the channels subscribed with the `watch_tasks` command.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use tauri::{ipc::Channel, AppHandle, Emitter, Manager, Runtime};
+
+use crate::models::{TaskInfo, TaskStatus, WatchTasksRequest};
+
+/// The task was scheduled, or is waiting for its next run or a retry.
+pub const SCHEDULED_EVENT: &str = "schedule-task://scheduled";
+/// A run of the task started.
+pub const STARTED_EVENT: &str = "schedule-task://started";
+/// The task has no run left and its last run succeeded.
+pub const COMPLETED_EVENT: &str = "schedule-task://completed";
+/// The task failed, or has no run left and its last run failed.
+pub const FAILED_EVENT: &str = "schedule-task://failed";
+/// The task was cancelled.
+pub const CANCELLED_EVENT: &str = "schedule-task://cancelled";
+/// The task was paused.
+pub const PAUSED_EVENT: &str = "schedule-task://paused";
+/// The task has no run left and its last run was missed and skipped.
+pub const SKIPPED_EVENT: &str = "schedule-task://skipped";
+
+/// Event telling that a task moved to `status`.
+pub fn status_event(status: &TaskStatus) -> &'static str {
+  match status {
+    TaskStatus::Scheduled => SCHEDULED_EVENT,
+    TaskStatus::Running => STARTED_EVENT,
+    TaskStatus::Completed => COMPLETED_EVENT,
+    TaskStatus::Failed => FAILED_EVENT,
+    TaskStatus::Cancelled => CANCELLED_EVENT,
+    TaskStatus::Paused => PAUSED_EVENT,
+    TaskStatus::Skipped => SKIPPED_EVENT,
+  }
+}
+
+/// Emits the event for the current status of a task to every window, and sends the task to the
+/// watchers it matches.
+pub(crate) fn emit_status<R: Runtime>(app: &AppHandle<R>, task: &TaskInfo) {
+  if let Err(e) = app.emit(status_event(&task.status), task) {
+    eprintln!("[schedule-task] Failed to emit the status of task {}: {}", task.task_id, e);
+  }
+  if let Some(watchers) = app.try_state::<TaskWatchers>() {
+    watchers.send(task);
+  }
+}
+
+/// Channels subscribed with `watch_tasks`, by watch id.
+#[derive(Default)]
+pub(crate) struct TaskWatchers {
+  next_id: Mutex<u32>,
+  watchers: Mutex<HashMap<u32, (WatchTasksRequest, Channel<TaskInfo>)>>,
+}
+
+impl TaskWatchers {
+  pub(crate) fn add(&self, filter: WatchTasksRequest, channel: Channel<TaskInfo>) -> u32 {
+    let mut next_id = self.next_id.lock().unwrap();
+    *next_id += 1;
+    self.watchers.lock().unwrap().insert(*next_id, (filter, channel));
+    *next_id
+  }
+
+  pub(crate) fn remove(&self, watch_id: u32) {
+    self.watchers.lock().unwrap().remove(&watch_id);
+  }
+
+  fn send(&self, task: &TaskInfo) {
+    // a channel fails once its window is gone, so it is dropped rather than retried
+    self.watchers.lock().unwrap()
+      .retain(|_, (filter, channel)| !filter.matches(task) || channel.send(task.clone()).is_ok());
+  }
+}
@@ -0,0 +1,246 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::Arc;
+use tauri::{async_runtime::JoinHandle, AppHandle, Runtime};
+use tokio_util::sync::CancellationToken;
+
+use crate::{AsyncScheduledTaskHandler, HandlerFuture, ScheduledTaskHandler, TaskParameters};
+
+/// Details of the run a handler is called for.
+#[derive(Debug, Clone)]
+pub struct TaskContext {
+  /// Id of the task, empty when the app was launched to run a task installed without its id
+  pub task_id: String,
+  pub task_name: String,
+  /// Attempt of the run, from 1, incremented by each retry of a failed run
+  pub attempt: u32,
+  /// ISO 8601 datetime the run is retried at if this attempt fails, `None` on the last attempt
+  pub next_retry_at: Option<String>,
+  /// Cancelled when the run times out or the task is cancelled, handlers should stop soon after
+  pub cancellation: CancellationToken,
+}
+
+impl TaskContext {
+  pub(crate) fn new(task_id: String, task_name: String) -> Self {
+    Self {
+      task_id,
+      task_name,
+      attempt: 1,
+      next_retry_at: None,
+      cancellation: CancellationToken::new(),
+    }
+  }
+}
+
+/// A task handler, sync or async, as given to [`crate::init_with_handler`], or the handlers
+/// registered per task name on a [`crate::Builder`].
+pub struct TaskHandler<R: Runtime>(Kind<R>);
+
+enum Kind<R: Runtime> {
+  Sync(Arc<dyn ScheduledTaskHandler<R> + Send + Sync>),
+  Async(Arc<dyn AsyncScheduledTaskHandler<R> + Send + Sync>),
+  Router(Arc<TaskRouter<R>>),
+}
+
+/// Handlers by task name, with the handler of the other names.
+pub(crate) struct TaskRouter<R: Runtime> {
+  pub(crate) routes: HashMap<String, TaskHandler<R>>,
+  pub(crate) fallback: Option<TaskHandler<R>>,
+}
+
+impl<R: Runtime> Clone for TaskHandler<R> {
+  fn clone(&self) -> Self {
+    match &self.0 {
+      Kind::Sync(handler) => Self(Kind::Sync(handler.clone())),
+      Kind::Async(handler) => Self(Kind::Async(handler.clone())),
+      Kind::Router(router) => Self(Kind::Router(router.clone())),
+    }
+  }
+}
+
+impl<R: Runtime> TaskHandler<R> {
+  pub(crate) fn router(router: TaskRouter<R>) -> Self {
+    Self(Kind::Router(Arc::new(router)))
+  }
+
+  /// Handles tasks with a closure taking the context of the run.
+  pub(crate) fn from_fn<F>(handler: F) -> Self
+  where
+    F: Fn(&TaskContext, TaskParameters, &AppHandle<R>) -> crate::Result<()> + Send + Sync + 'static,
+  {
+    Self(Kind::Sync(Arc::new(FnHandler(handler))))
+  }
+
+  /// Handles tasks with an async closure taking the context of the run.
+  pub(crate) fn from_async_fn<F, Fut>(handler: F) -> Self
+  where
+    F: Fn(TaskContext, TaskParameters, AppHandle<R>) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = crate::Result<()>> + Send + 'static,
+  {
+    Self(Kind::Async(Arc::new(AsyncFnHandler(handler))))
+  }
+
+  /// Whether a task with this name can be handled.
+  pub(crate) fn accepts(&self, task_name: &str) -> bool {
+    match &self.0 {
+      Kind::Router(router) => router.routes.contains_key(task_name) || router.fallback.is_some(),
+      _ => true,
+    }
+  }
+
+  /// Runs the handler for a task.
+  ///
+  /// Sync handlers run on the blocking thread pool, so that they do not hold up the async runtime.
+  pub(crate) async fn handle(&self, context: &TaskContext, parameters: TaskParameters, app: &AppHandle<R>) -> crate::Result<()> {
+    let handler = match &self.0 {
+      Kind::Router(router) => router.routes.get(&context.task_name)
+        .or(router.fallback.as_ref())
+        .ok_or_else(|| crate::Error::UnknownTask(context.task_name.clone()))?,
+      _ => self,
+    };
+    match &handler.0 {
+      // routers are only built from closures and plain handlers
+      Kind::Router(_) => Err(crate::Error::UnknownTask(context.task_name.clone())),
+      Kind::Sync(handler) => {
+        let (handler, context, app) = (handler.clone(), context.clone(), app.clone());
+        join(tauri::async_runtime::spawn_blocking(move || {
+          handler.handle_scheduled_task_with_context(&context, parameters, &app)
+        }))
+        .await
+      }
+      Kind::Async(handler) => {
+        let (handler, context, app) = (handler.clone(), context.clone(), app.clone());
+        join(tauri::async_runtime::spawn(async move {
+          handler.handle_scheduled_task_with_context(&context, parameters, &app).await
+        }))
+        .await
+      }
+    }
+  }
+}
+
+/// Awaits a handler run on its own task, so that a panic of the handler becomes an error.
+async fn join(handle: JoinHandle<crate::Result<()>>) -> crate::Result<()> {
+  match handle.await {
+    Ok(result) => result,
+    Err(tauri::Error::JoinError(e)) if e.is_panic() => {
+      let panic = e.into_panic();
+      let message = panic.downcast_ref::<&str>().map(|message| message.to_string())
+        .or_else(|| panic.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "unknown panic".to_string());
+      Err(crate::Error::HandlerPanicked(message))
+    }
+    Err(e) => Err(e.into()),
+  }
+}
+
+/// Turns a [`ScheduledTaskHandler`] or an [`AsyncScheduledTaskHandler`] into a [`TaskHandler`].
+///
+/// `K` is [`SyncHandler`] or [`AsyncHandler`] and only tells the two implementations apart, it is
+/// inferred from the handler.
+pub trait IntoTaskHandler<R: Runtime, K> {
+  fn into_task_handler(self) -> TaskHandler<R>;
+}
+
+/// Marks handlers implementing [`ScheduledTaskHandler`].
+pub struct SyncHandler;
+
+/// Marks handlers implementing [`AsyncScheduledTaskHandler`].
+pub struct AsyncHandler;
+
+impl<R: Runtime, H: ScheduledTaskHandler<R> + Send + Sync + 'static> IntoTaskHandler<R, SyncHandler> for H {
+  fn into_task_handler(self) -> TaskHandler<R> {
+    TaskHandler(Kind::Sync(Arc::new(self)))
+  }
+}
+
+impl<R: Runtime, H: AsyncScheduledTaskHandler<R> + Send + Sync + 'static> IntoTaskHandler<R, AsyncHandler> for H {
+  fn into_task_handler(self) -> TaskHandler<R> {
+    TaskHandler(Kind::Async(Arc::new(self)))
+  }
+}
+
+struct FnHandler<F>(F);
+
+impl<R: Runtime, F> ScheduledTaskHandler<R> for FnHandler<F>
+where
+  F: Fn(&TaskContext, TaskParameters, &AppHandle<R>) -> crate::Result<()>,
+{
+  fn handle_scheduled_task(&self, task_name: &str, parameters: TaskParameters, app: &AppHandle<R>) -> crate::Result<()> {
+    self.handle_scheduled_task_with_context(&TaskContext::new(String::new(), task_name.to_string()), parameters, app)
+  }
+
+  fn handle_scheduled_task_with_context(&self, context: &TaskContext, parameters: TaskParameters, app: &AppHandle<R>) -> crate::Result<()> {
+    (self.0)(context, parameters, app)
+  }
+}
+
+struct AsyncFnHandler<F>(F);
+
+impl<R: Runtime, F, Fut> AsyncScheduledTaskHandler<R> for AsyncFnHandler<F>
+where
+  F: Fn(TaskContext, TaskParameters, AppHandle<R>) -> Fut,
+  Fut: Future<Output = crate::Result<()>> + Send + 'static,
+{
+  fn handle_scheduled_task<'a>(
+    &'a self,
+    task_name: &'a str,
+    parameters: TaskParameters,
+    app: &'a AppHandle<R>,
+  ) -> HandlerFuture<'a> {
+    Box::pin((self.0)(TaskContext::new(String::new(), task_name.to_string()), parameters, app.clone()))
+  }
+
+  fn handle_scheduled_task_with_context<'a>(
+    &'a self,
+    context: &'a TaskContext,
+    parameters: TaskParameters,
+    app: &'a AppHandle<R>,
+  ) -> HandlerFuture<'a> {
+    Box::pin((self.0)(context.clone(), parameters, app.clone()))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use tauri::test::{mock_app, MockRuntime};
+
+  fn handle(handler: &TaskHandler<MockRuntime>, task_name: &str) -> crate::Result<()> {
+    let app = mock_app();
+    let context = TaskContext::new("1".to_string(), task_name.to_string());
+    tauri::async_runtime::block_on(handler.handle(&context, TaskParameters::default(), app.handle()))
+  }
+
+  #[test]
+  fn turns_panics_of_sync_handlers_into_errors() {
+    let handler = TaskHandler::from_fn(|_context, _parameters, _app| panic!("disk full"));
+    assert!(matches!(handle(&handler, "backup"), Err(crate::Error::HandlerPanicked(message)) if message == "disk full"));
+  }
+
+  #[test]
+  fn turns_panics_of_async_handlers_into_errors() {
+    let handler = TaskHandler::from_async_fn(|context, _parameters, _app| async move {
+      panic!("{} lost its connection", context.task_name)
+    });
+    assert!(matches!(handle(&handler, "sync"), Err(crate::Error::HandlerPanicked(message)) if message == "sync lost its connection"));
+  }
+
+  #[test]
+  fn passes_handler_errors_through() {
+    let handler = TaskHandler::from_fn(|_context, _parameters, _app| Err(crate::Error::Generic("offline".to_string())));
+    assert!(matches!(handle(&handler, "backup"), Err(crate::Error::Generic(message)) if message == "offline"));
+  }
+
+  #[test]
+  fn rejects_names_without_a_route() {
+    let router = TaskHandler::router(TaskRouter {
+      routes: HashMap::from([("backup".to_string(), TaskHandler::from_fn(|_context, _parameters, _app| Ok(())))]),
+      fallback: None,
+    });
+    assert!(router.accepts("backup"));
+    assert!(!router.accepts("cleanup"));
+    assert!(handle(&router, "backup").is_ok());
+    assert!(matches!(handle(&router, "cleanup"), Err(crate::Error::UnknownTask(name)) if name == "cleanup"));
+  }
+}
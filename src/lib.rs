@@ -1,21 +1,40 @@
 use tauri::{
-  plugin::{Builder, TauriPlugin}, AppHandle, Manager, Runtime
+  plugin::{Builder as PluginBuilder, TauriPlugin}, AppHandle, Manager, Runtime
 };
-use std::{collections::HashMap, ops::{Deref, DerefMut}};
+use serde::{de::DeserializeOwned, Serialize};
+use std::future::Future;
+use std::pin::Pin;
 use std::sync::Arc;
 
+pub use builder::Builder;
+pub use handler::{AsyncHandler, IntoTaskHandler, SyncHandler, TaskContext, TaskHandler};
 pub use models::*;
+pub use store::*;
+#[cfg(desktop)]
+pub use system::*;
 
 #[cfg(desktop)]
 mod desktop;
 #[cfg(mobile)]
 mod mobile;
+#[cfg(desktop)]
+mod recurrence;
+#[cfg(desktop)]
+mod rrule;
+#[cfg(desktop)]
+mod system;
 
+mod builder;
 mod commands;
 mod error;
+pub mod events;
+mod handler;
 mod models;
+mod store;
+mod timezone;
 
 pub use error::{Error, Result};
+pub use tokio_util::sync::CancellationToken;
 
 #[cfg(desktop)]
 use desktop::ScheduleTask;
@@ -35,73 +54,160 @@ impl<R: Runtime, T: Manager<R>> crate::ScheduleTaskExt<R> for T {
 
 /// Trait for handling scheduled task execution
 pub trait ScheduledTaskHandler<R: Runtime> {
-  fn handle_scheduled_task(&self, task_name: &str, parameters: HashMap<String, String>, app: &AppHandle<R>) -> Result<()>;
+  fn handle_scheduled_task(&self, task_name: &str, parameters: TaskParameters, app: &AppHandle<R>) -> Result<()>;
+
+  /// Handles a task knowing the details of the run, calls [`Self::handle_scheduled_task`] unless overridden
+  fn handle_scheduled_task_with_context(&self, context: &TaskContext, parameters: TaskParameters, app: &AppHandle<R>) -> Result<()> {
+    self.handle_scheduled_task(&context.task_name, parameters, app)
+  }
+}
+
+/// Future returned by an [`AsyncScheduledTaskHandler`].
+pub type HandlerFuture<'a> = Pin<Box<dyn Future<Output = Result<()>> + Send + 'a>>;
+
+/// Trait for handling scheduled task execution without blocking the async runtime
+pub trait AsyncScheduledTaskHandler<R: Runtime> {
+  fn handle_scheduled_task<'a>(
+    &'a self,
+    task_name: &'a str,
+    parameters: TaskParameters,
+    app: &'a AppHandle<R>,
+  ) -> HandlerFuture<'a>;
+
+  /// Handles a task knowing the details of the run, calls [`Self::handle_scheduled_task`] unless overridden
+  fn handle_scheduled_task_with_context<'a>(
+    &'a self,
+    context: &'a TaskContext,
+    parameters: TaskParameters,
+    app: &'a AppHandle<R>,
+  ) -> HandlerFuture<'a> {
+    self.handle_scheduled_task(&context.task_name, parameters, app)
+  }
+}
+
+/// A kind of task with typed parameters.
+///
+/// Tasks of a kind are scheduled with `schedule::<K>` and handled by the handler registered with
+/// [`Builder::task`] or [`Builder::async_task`], which gets the parameters as `K::Params`.
+///
+/// ```rust,ignore
+/// struct Backup;
+///
+/// impl TaskKind for Backup {
+///   const NAME: &'static str = "backup";
+///   type Params = BackupParams;
+/// }
+///
+/// app.schedule_task().schedule::<Backup>(ScheduleTime::Duration(60), BackupParams { target: "nas".into() }).await?;
+/// ```
+pub trait TaskKind {
+  /// Name the tasks of this kind are scheduled under
+  const NAME: &'static str;
+  /// Parameters of the tasks, a struct or a map
+  type Params: Serialize + DeserializeOwned;
 }
 
 /// Check if app was launched to run a scheduled task
-pub fn check_scheduled_task_args() -> Option<(String, HashMap<String, String>)> {
+pub fn check_scheduled_task_args() -> Option<(String, TaskParameters)> {
   let args: Vec<String> = std::env::args().collect();
-  
+  parse_scheduled_task_args(&args)
+}
+
+/// Reads the id of the task to run out of a `--task-id=` argument.
+///
+/// Tasks installed in an OS scheduler before ids were passed have none.
+pub(crate) fn scheduled_task_id<S: AsRef<str>>(args: &[S]) -> Option<String> {
+  args.iter().find_map(|arg| arg.as_ref().strip_prefix("--task-id=").map(str::to_string))
+}
+
+/// Reads the task name and parameters out of `--run-task=` and `--task-param=` arguments.
+pub(crate) fn parse_scheduled_task_args<S: AsRef<str>>(args: &[S]) -> Option<(String, TaskParameters)> {
   let mut task_name: Option<String> = None;
-  let mut parameters = HashMap::new();
+  let mut parameters = Vec::new();
   
-  for arg in &args {
+  for arg in args {
+    let arg = arg.as_ref();
     if let Some(name) = arg.strip_prefix("--run-task=") {
       task_name = Some(name.to_string());
     } else if let Some(param) = arg.strip_prefix("--task-param=") {
       if let Some((key, value)) = param.split_once('=') {
-        parameters.insert(key.to_string(), value.to_string());
+        parameters.push((key, value));
       }
     }
   }
   
-  task_name.map(|name| (name, parameters))
+  task_name.map(|name| (name, TaskParameters::from_strings(parameters)))
 }
 
-/// Initialize the plugin with a task handler
-pub fn init_with_handler<R: Runtime, H: ScheduledTaskHandler<R> + Send + Sync + 'static>(
+/// Initialize the plugin with a task handler, either a [`ScheduledTaskHandler`] or an [`AsyncScheduledTaskHandler`]
+pub fn init_with_handler<R: Runtime, H: IntoTaskHandler<R, K>, K>(
   handler: H,
-) -> TauriPlugin<R> {
-  let handler_arc = Arc::new(handler);
-  Builder::new("schedule-task")
+) -> TauriPlugin<R, Option<Config>> {
+  build(Some(handler.into_task_handler()), None)
+}
+
+/// Initialize the plugin with a task handler, keeping the tasks in `store` instead of the default store.
+///
+/// The store is only used on desktop; mobile tasks are kept by the OS schedulers.
+pub fn init_with_handler_and_store<R, H, K, S>(handler: H, store: S) -> TauriPlugin<R, Option<Config>>
+where
+  R: Runtime,
+  H: IntoTaskHandler<R, K>,
+  S: TaskStore + 'static,
+{
+  build(Some(handler.into_task_handler()), Some(Arc::new(store)))
+}
+
+/// Initializes the plugin.
+pub fn init<R: Runtime>() -> TauriPlugin<R, Option<Config>> {
+  build(None, None)
+}
+
+fn build<R: Runtime>(
+  handler: Option<TaskHandler<R>>,
+  store: Option<Arc<dyn TaskStore>>,
+) -> TauriPlugin<R, Option<Config>> {
+  PluginBuilder::<R, Option<Config>>::new("schedule-task")
     .invoke_handler(tauri::generate_handler![
       commands::schedule_task,
+      commands::update_task,
+      commands::pause_task,
+      commands::resume_task,
+      commands::run_task_now,
       commands::cancel_task,
-      commands::list_tasks
+      commands::cancel_all,
+      commands::cancel_by_name,
+      commands::list_tasks,
+      commands::get_task_history,
+      commands::watch_tasks,
+      commands::unwatch_tasks
     ])
     .setup(move |app, api| {
+      #[cfg(desktop)]
+      let config = api.config().clone().unwrap_or_default();
       #[cfg(mobile)]
-      let schedule_task = mobile::init(app, api, Some(handler_arc.clone()))?;
+      let schedule_task = {
+        let _ = &store;
+        mobile::init(app, api, handler.clone())?
+      };
       #[cfg(desktop)]
-      let schedule_task = desktop::init(app, api, Some(handler_arc.clone()))?;
+      let schedule_task = desktop::init(app, api, &config, handler.clone(), store.clone())?;
       app.manage(schedule_task);
+      app.manage(events::TaskWatchers::default());
       
       // Check if this is a scheduled task execution
       #[cfg(desktop)]
-      if let Some((task_name, parameters)) = check_scheduled_task_args() {
-        let _ = handler_arc.handle_scheduled_task(&task_name, parameters, app);
+      if let (Some(_), Some((task_name, parameters))) = (&handler, check_scheduled_task_args()) {
+        let task_id = scheduled_task_id(&std::env::args().collect::<Vec<_>>());
+        let schedule_task = app.state::<ScheduleTask<R>>();
+        let run = schedule_task.run_installed_task(task_id, task_name.clone(), parameters);
+        if let Err(e) = tauri::async_runtime::block_on(run) {
+          eprintln!("[schedule-task] Task {} failed: {}", task_name, e);
+          std::process::exit(1);
+        }
         std::process::exit(0);
       }
       Ok(())
     })
     .build()
 }
-
-/// Initializes the plugin.
-pub fn init<R: Runtime>() -> TauriPlugin<R> {
-  Builder::new("schedule-task")
-    .invoke_handler(tauri::generate_handler![
-      commands::schedule_task,
-      commands::cancel_task,
-      commands::list_tasks
-    ])
-    .setup(|app, api| {
-      #[cfg(mobile)]
-      let schedule_task = mobile::init(app, api, None)?;
-      #[cfg(desktop)]
-      let schedule_task = desktop::init(app, api, None)?;
-      app.manage(schedule_task);
-      Ok(())
-    })
-    .build()
-}
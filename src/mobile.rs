@@ -6,7 +6,7 @@ use tauri::{
 };
 use tokio_cron_scheduler::job::JobId;
 
-use crate::{models::*, ScheduleTaskExt, ScheduledTaskHandler};
+use crate::{events, models::*, ScheduleTaskExt, TaskContext, TaskHandler};
 
 #[cfg(target_os = "ios")]
 tauri::ios_plugin_binding!(init_plugin_schedule_task);
@@ -18,7 +18,7 @@ struct EventHandlerSet(bool);
 pub fn init<R: Runtime, C: DeserializeOwned>(
   app: &AppHandle<R>,
   api: PluginApi<R, C>,
-  handler: Option<Arc<dyn ScheduledTaskHandler<R> + Send + Sync>>,
+  handler: Option<TaskHandler<R>>,
 ) -> crate::Result<ScheduleTask<R>> {
   #[cfg(target_os = "android")]
   let handle = api.register_android_plugin("com.plugin.scheduletask", "ScheduleTaskPlugin")?;
@@ -43,7 +43,7 @@ pub struct ScheduleTask<R: Runtime> {
   scheduled_tasks: Arc<Mutex<HashMap<String, TaskInfo>>>,
   job_ids: Arc<Mutex<HashMap<String, JobId>>>,
   handle: PluginHandle<R>,
-  task_handler: Option<Arc<dyn ScheduledTaskHandler<R> + Send + Sync>>,
+  task_handler: Option<TaskHandler<R>>,
 }
 
 impl<R: Runtime> ScheduleTask<R> {
@@ -55,25 +55,101 @@ impl<R: Runtime> ScheduleTask<R> {
   }
 
   pub async fn schedule_task(&self, payload: ScheduleTaskRequest) -> crate::Result<ScheduleTaskResponse> {
+    if self.task_handler.as_ref().is_some_and(|handler| !handler.accepts(&payload.task_name)) {
+      return Err(crate::Error::UnknownTask(payload.task_name));
+    }
     self.set_task_handler()?;
+    // the native schedulers only carry string parameters
+    let mut payload = payload;
+    payload.parameters = payload.parameters.map(|parameters| parameters.to_strings().into_iter().collect());
+    // nor time zones, so datetimes are handed to them with their offset
+    if let Some(timezone) = &payload.timezone {
+      let zone = crate::timezone::parse_timezone(timezone)?;
+      if let ScheduleTime::DateTime(dt_str) = &payload.schedule_time {
+        let dt = crate::timezone::parse_datetime_in(dt_str, Some(&zone))?;
+        payload.schedule_time = ScheduleTime::DateTime(dt.with_timezone(&zone).to_rfc3339());
+      }
+    }
     self
       .handle
       .run_mobile_plugin("scheduleTask", payload)
       .map_err(Into::into)
   }
 
-  pub fn cancel_task(&self, payload: CancelTaskRequest) -> crate::Result<CancelTaskResponse> {
+  /// Schedules a task of kind `K`, see [`crate::TaskKind`].
+  pub async fn schedule<K: crate::TaskKind>(&self, when: ScheduleTime, params: K::Params) -> crate::Result<ScheduleTaskResponse> {
+    self.schedule_task(ScheduleTaskRequest::for_kind::<K>(when, &params)?).await
+  }
+
+  /// The native schedulers cannot change a task in place.
+  pub async fn update_task(&self, payload: UpdateTaskRequest) -> crate::Result<UpdateTaskResponse> {
+    Err(crate::Error::Generic(format!("Task {} cannot be updated on mobile, cancel and schedule it again", payload.task_id)))
+  }
+
+  pub async fn pause_task(&self, payload: PauseTaskRequest) -> crate::Result<UpdateTaskResponse> {
+    Err(crate::Error::Generic(format!("Task {} cannot be paused on mobile", payload.task_id)))
+  }
+
+  pub async fn resume_task(&self, payload: ResumeTaskRequest) -> crate::Result<UpdateTaskResponse> {
+    Err(crate::Error::Generic(format!("Task {} cannot be resumed on mobile", payload.task_id)))
+  }
+
+  /// Runs the handler for a task right away, as when the native scheduler fires it.
+  pub async fn run_now(&self, payload: RunTaskNowRequest) -> crate::Result<RunTaskNowResponse> {
+    let handler = self.task_handler.clone()
+      .ok_or_else(|| crate::Error::Generic("No task handler is registered".to_string()))?;
+    let listed = self.list_tasks()?.tasks.into_iter().find(|task| task.task_id == payload.task_id)
+      .ok_or_else(|| crate::Error::Generic(format!("Task {} not found", payload.task_id)))?;
+    let task = running_task_info(&listed.task_id, &listed.task_name, listed.parameters.unwrap_or_default());
+    spawn_run(self.app.clone(), handler, task.clone());
+    Ok(RunTaskNowResponse { task })
+  }
+
+  pub async fn cancel_task(&self, payload: CancelTaskRequest) -> crate::Result<CancelTaskResponse> {
     self
       .handle
       .run_mobile_plugin("cancelTask", payload)
       .map_err(Into::into)
   }
 
+  /// Runs are not recorded on mobile, so the history is always empty.
+  pub fn get_task_history(&self, _payload: GetTaskHistoryRequest) -> crate::Result<GetTaskHistoryResponse> {
+    Ok(GetTaskHistoryResponse {
+      runs: Vec::new(),
+      total: 0,
+    })
+  }
+
+  /// Cancels every task that has not finished yet.
+  pub async fn cancel_all(&self) -> crate::Result<CancelTasksResponse> {
+    self.cancel_matching(|_| true).await
+  }
+
+  /// Cancels every task with the given name that has not finished yet.
+  pub async fn cancel_by_name(&self, payload: CancelByNameRequest) -> crate::Result<CancelTasksResponse> {
+    self.cancel_matching(|task| task.task_name == payload.task_name).await
+  }
+
+  async fn cancel_matching(&self, matches: impl Fn(&TaskInfo) -> bool) -> crate::Result<CancelTasksResponse> {
+    let mut response = CancelTasksResponse::default();
+    for task in self.list_tasks()?.tasks.into_iter().filter(|task| !task.status.is_finished() && matches(task)) {
+      let cancelled = self.cancel_task(CancelTaskRequest { task_id: task.task_id.clone() }).await?;
+      response.record(task.task_id, cancelled);
+    }
+    Ok(response)
+  }
+
   pub fn list_tasks(&self) -> crate::Result<ListTasksResponse> {
-    self
+    let mut response: ListTasksResponse = self
       .handle
-      .run_mobile_plugin("listTasks", ())
-      .map_err(Into::into)
+      .run_mobile_plugin("listTasks", ())?;
+    for task in &mut response.tasks {
+      task.parameters = task.parameters.take().map(native_parameters);
+      if let Ok(scheduled_time) = crate::timezone::parse_datetime_in(&task.scheduled_time, None) {
+        task.set_scheduled_time(&scheduled_time);
+      }
+    }
+    Ok(response)
   }
 
   pub fn set_task_handler(&self) -> crate::Result<()> {
@@ -92,7 +168,6 @@ impl<R: Runtime> ScheduleTask<R> {
           handler: Channel::new(move |event| {
             let event_data = match event {
                 InvokeResponseBody::Json(payload) => {
-                  dbg!("Received event data: {}", &payload);
                   serde_json::from_str::<serde_json::Value>(&payload)
                     .ok()
                     //.map(|payload| payload.url)
@@ -101,25 +176,24 @@ impl<R: Runtime> ScheduleTask<R> {
                 _ => None,
             };
 
-            dbg!("Receiving the schedule task with this data: {:?}", &event_data);
             let (task_name, task_id, parameters) = match event_data {
               Some(data) => {
                 let task_name = data.get("task_name").and_then(|v| v.as_str()).unwrap_or_default().to_string();
                 let task_id = data.get("task_id").and_then(|v| v.as_str()).unwrap_or_default().to_string();
                 let parameters = data.get("parameters")
                   .and_then(|v| v.as_object())
-                  .map(|obj| obj.iter().map(|(k, v)| (k.clone(), v.as_str().unwrap_or_default().to_string())).collect())
+                  .map(|obj| native_parameters(TaskParameters(obj.clone())))
                   .unwrap_or_default();
                 (task_name, task_id, parameters)
               },
-              None => (String::new(), String::new(), HashMap::new()),
+              None => (String::new(), String::new(), TaskParameters::default()),
             };
 
             let state = app.state::<ScheduleTask<R>>();
             //if let Some(event_data) = event_data {
-              if let Some(handler) = &state.task_handler {
-                dbg!("Trying to run the event handler for task: {} with parameters {}", &task_name, &parameters);
-                handler.handle_scheduled_task(task_name.as_str(), parameters, &app).unwrap();
+              if let Some(handler) = state.task_handler.clone() {
+                // the channel callback is sync, so run the handler on the async runtime
+                spawn_run(app.clone(), handler, running_task_info(&task_id, &task_name, parameters));
               }
             // }
             Ok(())
@@ -129,6 +203,60 @@ impl<R: Runtime> ScheduleTask<R> {
   }
 }
 
+/// Runs the handler for a task, emitting its status events around the run.
+fn spawn_run<R: Runtime>(app: AppHandle<R>, handler: TaskHandler<R>, mut task: TaskInfo) {
+  tauri::async_runtime::spawn(async move {
+    events::emit_status(&app, &task);
+    let context = TaskContext::new(task.task_id.clone(), task.task_name.clone());
+    let result = handler.handle(&context, task.parameters.clone().unwrap_or_default(), &app).await;
+    if let Err(e) = &result {
+      eprintln!("[schedule-task] Task {} failed: {}", task.task_id, e);
+    }
+    task.status = if result.is_ok() { TaskStatus::Completed } else { TaskStatus::Failed };
+    task.error = result.err().map(|e| e.to_string());
+    task.finished_at = Some(chrono::Local::now().to_rfc3339());
+    events::emit_status(&app, &task);
+  });
+}
+
+/// Decodes the string values the native schedulers hand back, see [`TaskParameters::to_strings`];
+/// other values are kept as they are.
+fn native_parameters(parameters: TaskParameters) -> TaskParameters {
+  parameters.0.into_iter()
+    .map(|(key, value)| match value {
+      serde_json::Value::String(text) => (key, crate::models::string_value(&text)),
+      value => (key, value),
+    })
+    .collect()
+}
+
+/// What is known of a task run by the native scheduler, as the payload of its status events.
+fn running_task_info(task_id: &str, task_name: &str, parameters: TaskParameters) -> TaskInfo {
+  let mut task = TaskInfo {
+    task_id: task_id.to_string(),
+    task_name: task_name.to_string(),
+    scheduled_time: String::new(),
+    scheduled_time_utc: String::new(),
+    status: TaskStatus::Running,
+    parameters: Some(parameters),
+    run_count: 1,
+    remaining_runs: None,
+    schedule: None,
+    misfire_policy: MisfirePolicy::default(),
+    catch_up: false,
+    system_scheduler: None,
+    error: None,
+    finished_at: None,
+    retry_policy: None,
+    attempt: 1,
+    next_retry_at: None,
+    timeout: None,
+    tags: Vec::new(),
+    timezone: None,
+  };
+  task.set_scheduled_time(&chrono::Local::now());
+  task
+}
 
 #[derive(Serialize)]
 #[serde(rename_all = "camelCase")]
@@ -141,5 +269,5 @@ pub struct EventHandler {
 pub struct EventInfo {
   pub task_id: String,
   pub task_name: String,
-  pub parameters: HashMap<String, String>,
+  pub parameters: TaskParameters,
 }
\ No newline at end of file
@@ -1,5 +1,93 @@
-use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use chrono::{DateTime, Local, TimeZone, Utc};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::{BTreeMap, HashMap};
+use std::ops::{Deref, DerefMut};
+use std::path::PathBuf;
+
+/// Plugin configuration, read from `plugins.schedule-task` in `tauri.conf.json`.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Config {
+  /// OS scheduler the tasks it supports are installed in, so they run while the app is closed (desktop only)
+  #[serde(default)]
+  pub system_scheduler: Option<SystemSchedulerConfig>,
+  /// How long the runs of each task are kept in its history (desktop only)
+  #[serde(default)]
+  pub history: HistoryRetention,
+  /// How long finished tasks are kept in the store (desktop only)
+  #[serde(default)]
+  pub finished_tasks: FinishedTaskRetention,
+}
+
+/// Limits of the run history kept per task, runs beyond either of them are dropped.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct HistoryRetention {
+  /// Most runs kept per task, unlimited when missing
+  pub max_runs: Option<usize>,
+  /// Seconds a run is kept for, forever when missing
+  pub max_age: Option<u64>,
+}
+
+impl Default for HistoryRetention {
+  fn default() -> Self {
+    Self {
+      max_runs: Some(100),
+      max_age: None,
+    }
+  }
+}
+
+/// Limits of the finished (completed, failed, cancelled or skipped) tasks kept in the store, tasks
+/// beyond either of them are removed along with their history. Finished tasks are kept forever by default.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct FinishedTaskRetention {
+  /// Most finished tasks kept, unlimited when missing
+  pub max_tasks: Option<usize>,
+  /// Seconds a finished task is kept for after its last run, forever when missing
+  pub max_age: Option<u64>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum SystemSchedulerConfig {
+  /// The user's crontab, for cron schedules
+  #[serde(rename_all = "camelCase")]
+  Crontab {
+    /// `crontab` command to use instead of the one in `PATH`
+    command: Option<PathBuf>,
+  },
+  /// `systemd --user` timers, for cron schedules, one-time datetimes and unbounded intervals
+  #[serde(rename_all = "camelCase")]
+  Systemd {
+    /// Directory of the units, defaults to `~/.config/systemd/user`
+    unit_dir: Option<PathBuf>,
+    /// `systemctl` command to use instead of the one in `PATH`
+    systemctl: Option<PathBuf>,
+    /// Whether the timers wake the machine from suspend
+    #[serde(default)]
+    wake_system: bool,
+  },
+  /// launchd agents (macOS), for cron schedules and unbounded intervals
+  #[serde(rename_all = "camelCase")]
+  Launchd {
+    /// Directory of the plists, defaults to `~/Library/LaunchAgents`
+    agents_dir: Option<PathBuf>,
+    /// `launchctl` command to use instead of the one in `PATH`
+    launchctl: Option<PathBuf>,
+  },
+  /// Windows Task Scheduler, for one-time datetimes, intervals of whole minutes and daily, weekly or monthly cron schedules
+  #[serde(rename_all = "camelCase")]
+  TaskScheduler {
+    /// `schtasks` command to use instead of the one in `PATH`
+    schtasks: Option<PathBuf>,
+    /// Whether the tasks wake the machine from sleep
+    #[serde(default)]
+    wake_to_run: bool,
+  },
+}
 
 #[derive(Debug, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
@@ -18,6 +106,123 @@ pub struct PingResponse {
 pub enum ScheduleTime {
   DateTime(String), // ISO 8601 datetime string
   Duration(u64),    // Duration in seconds from now
+  Cron(String),     // Recurring cron expression (desktop only)
+  Interval(IntervalSchedule), // Fixed-interval repetition (desktop only)
+  Rrule(RRuleSchedule),       // RFC 5545 recurrence rule (desktop only)
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IntervalSchedule {
+  /// Seconds between two runs
+  pub every: u64,
+  /// ISO 8601 datetime of the first run, defaults to one interval from now
+  pub start_at: Option<String>,
+  /// ISO 8601 datetime after which the task stops repeating
+  pub end_at: Option<String>,
+  /// Maximum number of runs
+  pub max_runs: Option<u32>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RRuleSchedule {
+  /// iCalendar recurrence rule, e.g. `FREQ=MONTHLY;BYDAY=2TU`
+  pub rule: String,
+  /// ISO 8601 datetime the rule starts from (`DTSTART`), defaults to now
+  pub start_at: Option<String>,
+  /// ISO 8601 datetimes excluded from the recurrence (`EXDATE`)
+  pub exdates: Option<Vec<String>>,
+}
+
+/// Parameters of a task, a JSON object whose values can be of any type.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+#[serde(transparent)]
+pub struct TaskParameters(pub Map<String, Value>);
+
+impl TaskParameters {
+  /// Deserializes the parameters into `T`, failing with a message naming `T` and what did not match.
+  pub fn parse<T: DeserializeOwned>(&self) -> crate::Result<T> {
+    serde_json::from_value(Value::Object(self.0.clone())).map_err(|e| {
+      crate::Error::InvalidParameters(format!("expected {}: {}", std::any::type_name::<T>(), e))
+    })
+  }
+
+  /// Serializes `params`, a struct or a map, into parameters.
+  pub fn from_params<T: Serialize>(params: &T) -> crate::Result<Self> {
+    match serde_json::to_value(params) {
+      Ok(Value::Object(parameters)) => Ok(Self(parameters)),
+      Ok(value) => Err(crate::Error::InvalidParameters(format!(
+        "{} is not an object: {}", std::any::type_name::<T>(), value
+      ))),
+      Err(e) => Err(crate::Error::InvalidParameters(format!("{}: {}", std::any::type_name::<T>(), e))),
+    }
+  }
+
+  /// Value of a parameter holding a string.
+  pub fn get_str(&self, key: &str) -> Option<&str> {
+    self.0.get(key).and_then(Value::as_str)
+  }
+
+  /// The parameters as strings, for command lines and the native schedulers.
+  ///
+  /// Strings are kept as they are, other values are written as JSON after a `json:` prefix, which
+  /// strings starting with it get too. See [`Self::from_strings`].
+  pub(crate) fn to_strings(&self) -> BTreeMap<String, String> {
+    self.0.iter()
+      .map(|(key, value)| {
+        let value = match value {
+          Value::String(text) if !text.starts_with(JSON_PREFIX) => text.clone(),
+          value => format!("{}{}", JSON_PREFIX, value),
+        };
+        (key.clone(), value)
+      })
+      .collect()
+  }
+
+  /// Reads back parameters written by [`Self::to_strings`]: only values with the `json:` prefix are
+  /// read as JSON, so strings of tasks scheduled by older versions, e.g. `"7"`, stay strings.
+  pub(crate) fn from_strings<K: Into<String>, V: AsRef<str>>(parameters: impl IntoIterator<Item = (K, V)>) -> Self {
+    parameters.into_iter()
+      .map(|(key, value)| (key.into(), string_value(value.as_ref())))
+      .collect()
+  }
+}
+
+/// Prefix of the parameters written as JSON by [`TaskParameters::to_strings`].
+const JSON_PREFIX: &str = "json:";
+
+/// The value of a parameter written by [`TaskParameters::to_strings`].
+pub(crate) fn string_value(text: &str) -> Value {
+  text.strip_prefix(JSON_PREFIX)
+    .and_then(|json| serde_json::from_str(json).ok())
+    .unwrap_or_else(|| Value::String(text.to_string()))
+}
+
+impl Deref for TaskParameters {
+  type Target = Map<String, Value>;
+
+  fn deref(&self) -> &Self::Target {
+    &self.0
+  }
+}
+
+impl DerefMut for TaskParameters {
+  fn deref_mut(&mut self) -> &mut Self::Target {
+    &mut self.0
+  }
+}
+
+impl<K: Into<String>, V: Into<Value>> FromIterator<(K, V)> for TaskParameters {
+  fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+    Self(iter.into_iter().map(|(key, value)| (key.into(), value.into())).collect())
+  }
+}
+
+impl From<HashMap<String, String>> for TaskParameters {
+  fn from(parameters: HashMap<String, String>) -> Self {
+    parameters.into_iter().collect()
+  }
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -25,7 +230,84 @@ pub enum ScheduleTime {
 pub struct ScheduleTaskRequest {
   pub task_name: String,
   pub schedule_time: ScheduleTime,
-  pub parameters: Option<HashMap<String, String>>,
+  pub parameters: Option<TaskParameters>,
+  /// What to do with runs missed while the app was closed (desktop only)
+  #[serde(default)]
+  pub misfire_policy: Option<MisfirePolicy>,
+  /// How failed runs are retried, not at all when missing (desktop only)
+  #[serde(default)]
+  pub retry_policy: Option<RetryPolicy>,
+  /// Seconds a run may last before it fails, unlimited when missing (desktop only)
+  #[serde(default)]
+  pub timeout: Option<u64>,
+  /// Labels to find the task by, e.g. when watching tasks
+  #[serde(default)]
+  pub tags: Vec<String>,
+  /// IANA time zone the schedule is read in, e.g. `Europe/Berlin`, the system's when missing
+  #[serde(default)]
+  pub timezone: Option<String>,
+}
+
+impl ScheduleTaskRequest {
+  /// Request for a task of kind `K`, the other fields left to their defaults.
+  pub fn for_kind<K: crate::TaskKind>(schedule_time: ScheduleTime, params: &K::Params) -> crate::Result<Self> {
+    Ok(Self {
+      task_name: K::NAME.to_string(),
+      schedule_time,
+      parameters: Some(TaskParameters::from_params(params)?),
+      misfire_policy: None,
+      retry_policy: None,
+      timeout: None,
+      tags: Vec::new(),
+      timezone: None,
+    })
+  }
+}
+
+/// Retries of a failed run, each one waiting longer than the previous one.
+///
+/// The delay before attempt `n + 1` is `initial_delay * multiplier^(n - 1)`, capped at `max_delay`,
+/// then moved randomly by up to `jitter` times itself. It is counted from the start of the failed attempt.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct RetryPolicy {
+  /// Attempts of a run, the first one included
+  pub max_attempts: u32,
+  /// Seconds before the first retry
+  pub initial_delay: u64,
+  /// Factor applied to the delay after each retry
+  pub multiplier: f64,
+  /// Longest delay between two attempts, in seconds
+  pub max_delay: u64,
+  /// Fraction of the delay added or removed at random, from 0 to 1
+  pub jitter: f64,
+}
+
+impl Default for RetryPolicy {
+  fn default() -> Self {
+    Self {
+      max_attempts: 3,
+      initial_delay: 30,
+      multiplier: 2.0,
+      max_delay: 3600,
+      jitter: 0.1,
+    }
+  }
+}
+
+/// What happens to the runs of a task whose time passed while the app was not running.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum MisfirePolicy {
+  /// Run once for all the missed runs
+  #[default]
+  FireOnce,
+  /// Run once per missed run
+  FireAll,
+  /// Drop the missed runs and wait for the next one
+  Skip,
+  /// Run once if the last missed run is at most this many seconds old, skip otherwise
+  FireIfWithin(u64),
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -36,6 +318,54 @@ pub struct ScheduleTaskResponse {
   pub message: Option<String>,
 }
 
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PauseTaskRequest {
+  pub task_id: String,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ResumeTaskRequest {
+  pub task_id: String,
+}
+
+/// Changes to a task waiting for its next run, fields left out are kept.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UpdateTaskRequest {
+  pub task_id: String,
+  #[serde(default)]
+  pub task_name: Option<String>,
+  #[serde(default)]
+  pub schedule_time: Option<ScheduleTime>,
+  #[serde(default)]
+  pub parameters: Option<TaskParameters>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UpdateTaskResponse {
+  /// The task as updated, paused or resumed
+  pub task: TaskInfo,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RunTaskNowRequest {
+  pub task_id: String,
+  /// Whether the next scheduled run is skipped (desktop only)
+  #[serde(default)]
+  pub skip_next: bool,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RunTaskNowResponse {
+  /// The task as the run starts
+  pub task: TaskInfo,
+}
+
 #[derive(Debug, Clone, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct CancelTaskRequest {
@@ -49,6 +379,32 @@ pub struct CancelTaskResponse {
   pub message: Option<String>,
 }
 
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CancelByNameRequest {
+  pub task_name: String,
+}
+
+/// Outcome of cancelling several tasks at once.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CancelTasksResponse {
+  /// Ids of the tasks cancelled
+  pub cancelled: Vec<String>,
+  /// Why the other tasks could not be cancelled, by task id
+  pub errors: HashMap<String, String>,
+}
+
+impl CancelTasksResponse {
+  pub(crate) fn record(&mut self, task_id: String, response: CancelTaskResponse) {
+    if response.success {
+      self.cancelled.push(task_id);
+    } else {
+      self.errors.insert(task_id, response.message.unwrap_or_default());
+    }
+  }
+}
+
 #[derive(Debug, Clone, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct ListTasksResponse {
@@ -60,12 +416,63 @@ pub struct ListTasksResponse {
 pub struct TaskInfo {
   pub task_id: String,
   pub task_name: String,
+  /// ISO 8601 datetime of the next run, in the time zone of the task
   pub scheduled_time: String,
+  /// The same datetime in UTC
+  #[serde(default)]
+  pub scheduled_time_utc: String,
   pub status: TaskStatus,
-  pub parameters: Option<HashMap<String, String>>,
+  pub parameters: Option<TaskParameters>,
+  #[serde(default)]
+  pub run_count: u32,
+  pub remaining_runs: Option<u32>,
+  /// Schedule the task was created with (desktop only)
+  pub schedule: Option<ScheduleTime>,
+  #[serde(default)]
+  pub misfire_policy: MisfirePolicy,
+  /// Whether the current, or last, run made up for a run missed while the app was closed
+  #[serde(default)]
+  pub catch_up: bool,
+  /// OS scheduler running the task while the app is closed, e.g. `crontab` (desktop only)
+  #[serde(default)]
+  pub system_scheduler: Option<String>,
+  /// Error of the last run, `None` when it succeeded (desktop only)
+  #[serde(default)]
+  pub error: Option<String>,
+  /// ISO 8601 datetime the last run finished at (desktop only)
+  #[serde(default)]
+  pub finished_at: Option<String>,
+  /// How failed runs are retried (desktop only)
+  #[serde(default)]
+  pub retry_policy: Option<RetryPolicy>,
+  /// Attempt of the current, or last, run, from 1 (desktop only)
+  #[serde(default)]
+  pub attempt: u32,
+  /// ISO 8601 datetime a failed run is retried at (desktop only)
+  #[serde(default)]
+  pub next_retry_at: Option<String>,
+  /// Seconds a run may last before it fails (desktop only)
+  #[serde(default)]
+  pub timeout: Option<u64>,
+  #[serde(default)]
+  pub tags: Vec<String>,
+  /// IANA time zone the schedule is read in, the system's when missing
+  #[serde(default)]
+  pub timezone: Option<String>,
 }
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
+impl TaskInfo {
+  /// Sets the next run of the task, written in its time zone, or the system's when it has none.
+  pub(crate) fn set_scheduled_time<Tz: TimeZone>(&mut self, at: &DateTime<Tz>) {
+    self.scheduled_time = match self.timezone.as_deref().and_then(|zone| crate::timezone::parse_timezone(zone).ok()) {
+      Some(zone) => at.with_timezone(&zone).to_rfc3339(),
+      None => at.with_timezone(&Local).to_rfc3339(),
+    };
+    self.scheduled_time_utc = at.with_timezone(&Utc).to_rfc3339();
+  }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub enum TaskStatus {
   Scheduled,
@@ -73,4 +480,148 @@ pub enum TaskStatus {
   Completed,
   Failed,
   Cancelled,
+  /// Kept without running until resumed (desktop only)
+  Paused,
+  /// Ended without running its last run, which was missed and skipped by the misfire policy (desktop only)
+  Skipped,
+}
+
+impl TaskStatus {
+  /// Whether the task will never run again.
+  pub fn is_finished(&self) -> bool {
+    matches!(self, TaskStatus::Completed | TaskStatus::Failed | TaskStatus::Cancelled | TaskStatus::Skipped)
+  }
+}
+
+/// Which tasks a `watch_tasks` subscription streams, every task when empty.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WatchTasksRequest {
+  /// Only tasks with this name
+  #[serde(default)]
+  pub task_name: Option<String>,
+  /// Only tasks with this tag
+  #[serde(default)]
+  pub tag: Option<String>,
+}
+
+impl WatchTasksRequest {
+  pub fn matches(&self, task: &TaskInfo) -> bool {
+    self.task_name.as_ref().map_or(true, |task_name| *task_name == task.task_name)
+      && self.tag.as_ref().map_or(true, |tag| task.tags.contains(tag))
+  }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WatchTasksResponse {
+  /// Id to pass to `unwatch_tasks`
+  pub watch_id: u32,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UnwatchTasksRequest {
+  pub watch_id: u32,
+}
+
+/// A finished run of a task, as kept in its history.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TaskRun {
+  pub task_id: String,
+  /// ISO 8601 datetime the run started at
+  pub started_at: String,
+  /// ISO 8601 datetime the run finished at
+  pub finished_at: String,
+  pub duration_ms: u64,
+  pub outcome: RunOutcome,
+  pub error: Option<String>,
+  /// Attempt of the run, from 1
+  pub attempt: u32,
+  pub trigger: RunTrigger,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum RunOutcome {
+  Succeeded,
+  Failed,
+  TimedOut,
+  /// The task was cancelled while running
+  Cancelled,
+}
+
+/// Why a run started.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum RunTrigger {
+  /// The schedule of the task was due
+  Schedule,
+  /// Makes up for a run missed while the app was closed
+  CatchUp,
+  /// Retries a failed run
+  Retry,
+  /// Run out of band with `run_task_now`
+  Manual,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GetTaskHistoryRequest {
+  pub task_id: String,
+  /// Runs to skip, most recent first
+  #[serde(default)]
+  pub offset: usize,
+  /// Most runs to return, all of them when missing
+  #[serde(default)]
+  pub limit: Option<usize>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GetTaskHistoryResponse {
+  /// Runs of the page, most recent first
+  pub runs: Vec<TaskRun>,
+  /// Runs in the whole history of the task
+  pub total: usize,
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use serde_json::json;
+
+  #[test]
+  fn round_trips_parameters_through_strings() {
+    let parameters: TaskParameters = serde_json::from_value(json!({
+      "number": "42",
+      "quoted": "\"x\"",
+      "empty": "",
+      "literal": "true",
+      "prefixed": "json:{}",
+      "count": 42,
+      "ratio": 0.5,
+      "enabled": false,
+      "nothing": null,
+      "list": [1, "two"],
+      "nested": { "target": { "host": "nas", "port": 22 }, "tags": [] },
+    }))
+    .unwrap();
+    let strings = parameters.to_strings();
+    assert_eq!(strings["number"], "42");
+    assert_eq!(strings["empty"], "");
+    assert_eq!(strings["count"], "json:42");
+    assert_eq!(strings["prefixed"], "json:\"json:{}\"");
+    assert_eq!(TaskParameters::from_strings(strings), parameters);
+  }
+
+  #[test]
+  fn reads_unprefixed_strings_as_strings() {
+    let parameters = TaskParameters::from_strings([("count", "7"), ("enabled", "true"), ("nothing", "null"), ("broken", "json:{")]);
+    assert_eq!(parameters.get_str("count"), Some("7"));
+    assert_eq!(parameters.get_str("enabled"), Some("true"));
+    assert_eq!(parameters.get_str("nothing"), Some("null"));
+    assert_eq!(parameters.get_str("broken"), Some("json:{"));
+  }
 }
@@ -0,0 +1,275 @@
+use chrono::{DateTime, Duration, Local, TimeZone, Utc};
+use chrono_tz::Tz;
+use croner::Cron;
+use tokio_cron_scheduler::Job;
+
+use crate::models::{IntervalSchedule, RRuleSchedule, ScheduleTime};
+use crate::rrule::RRule;
+use crate::timezone::{from_local, parse_datetime_in, parse_timezone};
+
+/// How the runs of a task follow each other, built from its (normalized) [`ScheduleTime`].
+///
+/// Cron expressions and recurrence rules are matched on the local time of `timezone`, or of the
+/// system when it is `None`.
+#[derive(Debug, Clone)]
+pub(crate) enum Recurrence {
+  Once(DateTime<Local>),
+  Cron { schedule: String, cron: Cron, timezone: Option<Tz> },
+  Interval { rule: Interval, max_runs: Option<u32> },
+  Rrule { rule: RRule, timezone: Option<Tz> },
+}
+
+impl Recurrence {
+  /// Builds the recurrence of `schedule`, whose datetimes without an offset are read in `timezone`.
+  pub(crate) fn from_schedule(schedule: &ScheduleTime, timezone: Option<&str>) -> crate::Result<Self> {
+    let timezone = timezone.map(parse_timezone).transpose()?;
+    let parse_datetime = |dt_str: &str| parse_datetime_in(dt_str, timezone.as_ref()).map(|dt| dt.with_timezone(&Local));
+    match schedule {
+      ScheduleTime::DateTime(dt_str) => Ok(Recurrence::Once(parse_datetime(dt_str)?)),
+      ScheduleTime::Duration(seconds) => Ok(Recurrence::Once(Local::now() + Duration::seconds(*seconds as i64))),
+      ScheduleTime::Cron(expression) => {
+        let (schedule, cron) = parse_cron(expression)?;
+        Ok(Recurrence::Cron { schedule, cron, timezone })
+      }
+      ScheduleTime::Interval(interval) => {
+        if interval.every == 0 {
+          return Err(crate::Error::Generic("Interval must be at least one second".to_string()));
+        }
+        if interval.max_runs == Some(0) {
+          return Err(crate::Error::Generic("Interval task must run at least once".to_string()));
+        }
+        let every = Duration::seconds(interval.every as i64);
+        let rule = Interval {
+          first_run: match &interval.start_at {
+            Some(start_at) => parse_datetime(start_at)?,
+            None => Local::now() + every,
+          },
+          every,
+          end_at: interval.end_at.as_deref().map(parse_datetime).transpose()?,
+        };
+        Ok(Recurrence::Interval { rule, max_runs: interval.max_runs })
+      }
+      ScheduleTime::Rrule(schedule) => {
+        let dtstart = match &schedule.start_at {
+          Some(start_at) => parse_datetime(start_at)?,
+          None => Local::now(),
+        };
+        let exdates = schedule.exdates.iter().flatten()
+          .map(|exdate| parse_datetime(exdate).map(|exdate| exdate.with_timezone(&Utc)))
+          .collect::<crate::Result<Vec<_>>>()?;
+        let dtstart = match &timezone {
+          Some(zone) => dtstart.with_timezone(zone).naive_local(),
+          None => dtstart.naive_local(),
+        };
+        let rule = RRule::parse(&schedule.rule, dtstart)?.with_exdates(exdates);
+        Ok(Recurrence::Rrule { rule, timezone })
+      }
+    }
+  }
+
+  /// Pins the parts of `schedule` that are relative to the time it is made (durations and
+  /// missing start dates), so that the same runs are computed after a restart.
+  ///
+  /// Datetimes without an offset are pinned to the instant they stand for in `timezone`, except
+  /// the start of a recurrence rule, which stays a local time of it.
+  pub(crate) fn normalize(schedule: &ScheduleTime, timezone: Option<&str>) -> ScheduleTime {
+    let now = Local::now();
+    let zone = timezone.and_then(|timezone| parse_timezone(timezone).ok());
+    let pin = |dt_str: &String| match &zone {
+      Some(zone) => parse_datetime_in(dt_str, Some(zone))
+        .map(|dt| dt.with_timezone(zone).to_rfc3339())
+        .unwrap_or_else(|_| dt_str.clone()),
+      None => dt_str.clone(),
+    };
+    match schedule {
+      ScheduleTime::Duration(seconds) => ScheduleTime::DateTime((now + Duration::seconds(*seconds as i64)).to_rfc3339()),
+      ScheduleTime::DateTime(dt_str) => ScheduleTime::DateTime(pin(dt_str)),
+      ScheduleTime::Interval(interval) => ScheduleTime::Interval(IntervalSchedule {
+        start_at: Some(match &interval.start_at {
+          Some(start_at) => pin(start_at),
+          None => (now + Duration::seconds(interval.every as i64)).to_rfc3339(),
+        }),
+        end_at: interval.end_at.as_ref().map(pin),
+        ..interval.clone()
+      }),
+      ScheduleTime::Rrule(rrule) if rrule.start_at.is_none() => ScheduleTime::Rrule(RRuleSchedule {
+        start_at: Some(now.to_rfc3339()),
+        ..rrule.clone()
+      }),
+      schedule => schedule.clone(),
+    }
+  }
+
+  /// The first run of a newly scheduled task.
+  pub(crate) fn first_run(&self, now: &DateTime<Local>) -> Option<DateTime<Local>> {
+    match self {
+      Recurrence::Once(run_at) => Some(*run_at),
+      // DTSTART itself is the first occurrence when it matches the rule
+      Recurrence::Rrule { rule, timezone } => {
+        let dtstart = match timezone {
+          Some(zone) => rule.start(zone).with_timezone(&Local),
+          None => rule.start(&Local),
+        };
+        match dtstart {
+          dtstart if dtstart > *now => self.next_after(&(dtstart - Duration::seconds(1)), 0),
+          _ => self.next_after(now, 0),
+        }
+      }
+      recurrence => recurrence.next_after(now, 0),
+    }
+  }
+
+  /// The run following `after`, once the task has run `runs` times.
+  ///
+  /// `runs` only bounds intervals with `max_runs`: the `COUNT` of a recurrence rule is tallied
+  /// from its `DTSTART`.
+  pub(crate) fn next_after(&self, after: &DateTime<Local>, runs: u32) -> Option<DateTime<Local>> {
+    match self {
+      Recurrence::Once(_) => None,
+      Recurrence::Cron { cron, timezone: Some(zone), .. } => {
+        next_zoned_cron_occurrence(cron, &after.with_timezone(zone)).map(|next| next.with_timezone(&Local))
+      }
+      Recurrence::Cron { cron, timezone: None, .. } => next_cron_occurrence(cron, after),
+      Recurrence::Interval { max_runs: Some(max_runs), .. } if runs >= *max_runs => None,
+      Recurrence::Interval { rule, .. } => rule.next_after(after),
+      Recurrence::Rrule { rule, timezone: Some(zone) } => {
+        rule.next_after(&after.with_timezone(zone)).map(|next| next.with_timezone(&Local))
+      }
+      Recurrence::Rrule { rule, timezone: None } => rule.next_after(after),
+    }
+  }
+}
+
+pub(crate) fn parse_datetime(dt_str: &str) -> crate::Result<DateTime<Local>> {
+  Ok(parse_datetime_in(dt_str, None)?.with_timezone(&Local))
+}
+
+/// Parses a cron expression for the desktop `JobScheduler`.
+///
+/// Accepts the classic five-field syntax, the six-field syntax (with seconds) used by
+/// `tokio-cron-scheduler` and the English phrases it understands (e.g. `every 15 minutes`).
+/// Returns the normalized six-field expression alongside the parsed schedule.
+pub(crate) fn parse_cron(expression: &str) -> crate::Result<(String, Cron)> {
+  let expression = expression.trim();
+  let with_seconds = format!("0 {}", expression);
+  let schedule = if expression.split_whitespace().count() == 5 && parse_six_fields(&with_seconds).is_ok() {
+    with_seconds
+  } else {
+    Job::schedule_to_cron(expression)
+      .map_err(|e| crate::Error::Generic(format!("Invalid cron expression '{}': {}", expression, e)))?
+  };
+  let cron = parse_six_fields(&schedule)?;
+  Ok((schedule, cron))
+}
+
+/// Returns the first occurrence of `cron` strictly after `after`.
+pub(crate) fn next_cron_occurrence<Tz: TimeZone>(cron: &Cron, after: &DateTime<Tz>) -> Option<DateTime<Tz>> {
+  cron.find_next_occurrence(after, false).ok()
+}
+
+/// Returns the first occurrence of `cron` strictly after `after`, with the local times skipped or
+/// repeated by DST changes read as [`from_local`] does.
+///
+/// A local time repeated when the clocks go back only runs the first time around.
+fn next_zoned_cron_occurrence<Tz: TimeZone>(cron: &Cron, after: &DateTime<Tz>) -> Option<DateTime<Tz>> {
+  let zone = after.timezone();
+  // croner is given local times as UTC ones, so that it does not apply DST rules of its own
+  let mut local = after.naive_local();
+  loop {
+    local = cron.find_next_occurrence(&local.and_utc(), false).ok()?.naive_utc();
+    let next = from_local(&zone, &local)?;
+    if next > *after {
+      return Some(next);
+    }
+  }
+}
+
+fn parse_six_fields(expression: &str) -> crate::Result<Cron> {
+  Cron::new(expression)
+    .with_seconds_required()
+    .with_dom_and_dow()
+    .parse()
+    .map_err(|e| crate::Error::Generic(format!("Invalid cron expression '{}': {}", expression, e)))
+}
+
+/// Fixed-interval recurrence anchored at its first run.
+#[derive(Debug, Clone)]
+pub(crate) struct Interval {
+  pub first_run: DateTime<Local>,
+  pub every: Duration,
+  pub end_at: Option<DateTime<Local>>,
+}
+
+impl Interval {
+  /// Returns the first run strictly after `after`, unless it falls past the end date.
+  pub(crate) fn next_after(&self, after: &DateTime<Local>) -> Option<DateTime<Local>> {
+    let next = if *after < self.first_run {
+      self.first_run
+    } else {
+      let every = self.every.num_milliseconds();
+      let periods = after.signed_duration_since(self.first_run).num_milliseconds() / every + 1;
+      self.first_run + Duration::milliseconds(every * periods)
+    };
+    match self.end_at {
+      Some(end_at) if next > end_at => None,
+      _ => Some(next),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use chrono::{Days, Timelike};
+
+  /// The next `count` occurrences of a six-field cron expression in Berlin, after `after`.
+  fn berlin(expression: &str, after: &str, count: usize) -> Vec<String> {
+    let cron = parse_six_fields(expression).unwrap();
+    let zone = parse_timezone("Europe/Berlin").unwrap();
+    let mut at = DateTime::parse_from_rfc3339(after).unwrap().with_timezone(&zone);
+    (0..count)
+      .map(|_| {
+        at = next_zoned_cron_occurrence(&cron, &at).unwrap();
+        at.format("%d %H:%M%:z").to_string()
+      })
+      .collect()
+  }
+
+  #[test]
+  fn runs_zoned_cron_occurrences_skipped_by_spring_forward_later() {
+    // 2026-03-29 has no 02:30 in Berlin, that run happens at 03:30
+    assert_eq!(berlin("0 30 2 * * *", "2026-03-27T12:00:00+01:00", 3), ["28 02:30+01:00", "29 03:30+02:00", "30 02:30+02:00"]);
+    // runs every half hour go from 01:30 straight to 03:00, running once for the skipped hour
+    assert_eq!(berlin("0 */30 * * * *", "2026-03-29T01:00:00+01:00", 4), [
+      "29 01:30+01:00",
+      "29 03:00+02:00",
+      "29 03:30+02:00",
+      "29 04:00+02:00",
+    ]);
+  }
+
+  #[test]
+  fn runs_zoned_cron_occurrences_repeated_by_fall_back_once() {
+    // 2026-10-25 has 02:30 twice in Berlin, only the first one runs
+    assert_eq!(berlin("0 30 2 * * *", "2026-10-24T12:00:00+02:00", 3), ["25 02:30+02:00", "26 02:30+01:00", "27 02:30+01:00"]);
+    assert_eq!(berlin("0 */30 * * * *", "2026-10-25T02:00:00+02:00", 3), ["25 02:30+02:00", "25 03:00+01:00", "25 03:30+01:00"]);
+  }
+
+  #[test]
+  fn runs_only_the_occurrences_left_of_rules_started_in_the_past() {
+    let now = Local::now();
+    let start_at = (now - Duration::days(3) + Duration::hours(1)).with_nanosecond(0).unwrap();
+    let schedule = ScheduleTime::Rrule(RRuleSchedule {
+      rule: "FREQ=DAILY;COUNT=5".to_string(),
+      start_at: Some(start_at.to_rfc3339()),
+      exdates: None,
+    });
+    let recurrence = Recurrence::from_schedule(&schedule, None).unwrap();
+    // the first three occurrences are past, whatever the task ran
+    let first_run = recurrence.first_run(&now).unwrap();
+    assert_eq!(first_run, start_at.checked_add_days(Days::new(3)).unwrap());
+    let last_run = recurrence.next_after(&first_run, 1).unwrap();
+    assert_eq!(last_run, start_at.checked_add_days(Days::new(4)).unwrap());
+    assert!(recurrence.next_after(&last_run, 2).is_none());
+  }
+}
@@ -0,0 +1,672 @@
+use chrono::{DateTime, Datelike, Duration, Months, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Timelike, Utc, Weekday};
+
+use crate::timezone::from_local;
+
+/// Upper bound on the periods walked in a row without an occurrence, so that rules which can
+/// never match (e.g. `FREQ=YEARLY;BYMONTH=2;BYMONTHDAY=30`) terminate.
+const MAX_PERIODS: i64 = 500_000;
+
+/// How far past the searched instant a rule is followed before giving up.
+const MAX_YEARS_AHEAD: i32 = 200;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Frequency {
+  Yearly,
+  Monthly,
+  Weekly,
+  Daily,
+  Hourly,
+  Minutely,
+  Secondly,
+}
+
+#[derive(Debug, Clone)]
+enum Until {
+  Utc(DateTime<Utc>),
+  Floating(NaiveDateTime),
+}
+
+/// An RFC 5545 recurrence rule anchored at its `DTSTART`, with its excluded dates.
+///
+/// Occurrences are generated in the wall-clock time of the `DTSTART`; local times that do not
+/// exist in the target time zone (DST gaps) move forward by the length of the gap, and repeated
+/// ones are their first occurrence, see [`from_local`].
+#[derive(Debug, Clone)]
+pub(crate) struct RRule {
+  dtstart: NaiveDateTime,
+  freq: Frequency,
+  interval: u32,
+  count: Option<u32>,
+  until: Option<Until>,
+  by_second: Vec<u32>,
+  by_minute: Vec<u32>,
+  by_hour: Vec<u32>,
+  by_day: Vec<(Option<i32>, Weekday)>,
+  by_month_day: Vec<i32>,
+  by_year_day: Vec<i32>,
+  by_week_no: Vec<i32>,
+  by_month: Vec<u32>,
+  by_set_pos: Vec<i32>,
+  week_start: Weekday,
+  exdates: Vec<DateTime<Utc>>,
+}
+
+impl RRule {
+  /// Parses an `RRULE` value such as `FREQ=MONTHLY;BYDAY=2TU`, optionally prefixed with `RRULE:`.
+  pub(crate) fn parse(rule: &str, dtstart: NaiveDateTime) -> crate::Result<Self> {
+    let rule = rule.trim();
+    let rule = rule.strip_prefix("RRULE:").unwrap_or(rule);
+    let mut parsed = RRule {
+      dtstart: dtstart.with_nanosecond(0).unwrap_or(dtstart),
+      freq: Frequency::Daily,
+      interval: 1,
+      count: None,
+      until: None,
+      by_second: Vec::new(),
+      by_minute: Vec::new(),
+      by_hour: Vec::new(),
+      by_day: Vec::new(),
+      by_month_day: Vec::new(),
+      by_year_day: Vec::new(),
+      by_week_no: Vec::new(),
+      by_month: Vec::new(),
+      by_set_pos: Vec::new(),
+      week_start: Weekday::Mon,
+      exdates: Vec::new(),
+    };
+    let mut freq = None;
+
+    for part in rule.split(';').filter(|part| !part.is_empty()) {
+      let (name, value) = part
+        .split_once('=')
+        .ok_or_else(|| invalid(format!("malformed part '{}'", part)))?;
+      match name.to_ascii_uppercase().as_str() {
+        "FREQ" => freq = Some(parse_frequency(value)?),
+        "INTERVAL" => {
+          parsed.interval = value.parse().map_err(|_| invalid(format!("invalid INTERVAL '{}'", value)))?;
+          if parsed.interval == 0 {
+            return Err(invalid("INTERVAL must be positive".to_string()));
+          }
+        }
+        "COUNT" => parsed.count = Some(value.parse().map_err(|_| invalid(format!("invalid COUNT '{}'", value)))?),
+        "UNTIL" => parsed.until = Some(parse_until(value)?),
+        "BYSECOND" => parsed.by_second = parse_list(name, value, 0, 60)?,
+        "BYMINUTE" => parsed.by_minute = parse_list(name, value, 0, 59)?,
+        "BYHOUR" => parsed.by_hour = parse_list(name, value, 0, 23)?,
+        "BYDAY" => parsed.by_day = value.split(',').map(parse_weekday_num).collect::<crate::Result<_>>()?,
+        "BYMONTHDAY" => parsed.by_month_day = parse_signed_list(name, value, 31)?,
+        "BYYEARDAY" => parsed.by_year_day = parse_signed_list(name, value, 366)?,
+        "BYWEEKNO" => parsed.by_week_no = parse_signed_list(name, value, 53)?,
+        "BYMONTH" => parsed.by_month = parse_list(name, value, 1, 12)?,
+        "BYSETPOS" => parsed.by_set_pos = parse_signed_list(name, value, 366)?,
+        "WKST" => parsed.week_start = parse_weekday(value)?,
+        _ => return Err(invalid(format!("unsupported part '{}'", name))),
+      }
+    }
+
+    parsed.freq = freq.ok_or_else(|| invalid("FREQ is required".to_string()))?;
+    if parsed.count.is_some() && parsed.until.is_some() {
+      return Err(invalid("COUNT and UNTIL cannot be combined".to_string()));
+    }
+    let ordinals_allowed = matches!(parsed.freq, Frequency::Monthly)
+      || (parsed.freq == Frequency::Yearly && parsed.by_week_no.is_empty());
+    if !ordinals_allowed && parsed.by_day.iter().any(|(ordinal, _)| ordinal.is_some()) {
+      return Err(invalid("numeric BYDAY values are only allowed with FREQ=MONTHLY or FREQ=YEARLY".to_string()));
+    }
+    if parsed.freq == Frequency::Weekly && !parsed.by_month_day.is_empty() {
+      return Err(invalid("BYMONTHDAY cannot be used with FREQ=WEEKLY".to_string()));
+    }
+    if !parsed.by_week_no.is_empty() && parsed.freq != Frequency::Yearly {
+      return Err(invalid("BYWEEKNO is only allowed with FREQ=YEARLY".to_string()));
+    }
+    Ok(parsed)
+  }
+
+  /// The `DTSTART` of the rule, a local time of `tz`.
+  pub(crate) fn start<Tz: TimeZone>(&self, tz: &Tz) -> DateTime<Tz> {
+    from_local(tz, &self.dtstart).unwrap_or_else(|| Utc::now().with_timezone(tz))
+  }
+
+  /// Excludes the given instants (`EXDATE`) from the recurrence.
+  pub(crate) fn with_exdates(mut self, exdates: Vec<DateTime<Utc>>) -> Self {
+    self.exdates = exdates;
+    self
+  }
+
+  /// Returns the first occurrence strictly after `after`, in the time zone of `after`.
+  ///
+  /// With `COUNT`, the occurrences are tallied from `DTSTART`, excluded ones included, whatever
+  /// the task did with them: runs missed or skipped still use up the rule.
+  pub(crate) fn next_after<Tz: TimeZone>(&self, after: &DateTime<Tz>) -> Option<DateTime<Tz>> {
+    let Some(count) = self.count else {
+      return self.occurrences(after, self.period_index(&after.naive_local()))
+        .find(|occurrence| occurrence > after && !self.excludes(occurrence));
+    };
+    self.occurrences(after, 0)
+      .take(count as usize)
+      .find(|occurrence| occurrence > after && !self.excludes(occurrence))
+  }
+
+  /// The occurrences of the rule from the period `first_period` on, in the time zone of `after`,
+  /// excluded ones included.
+  ///
+  /// They end with `UNTIL`, [`MAX_YEARS_AHEAD`] years past `after`, or after [`MAX_PERIODS`]
+  /// periods in a row without any.
+  fn occurrences<'a, Tz: TimeZone + 'a>(&'a self, after: &DateTime<Tz>, first_period: i64) -> impl Iterator<Item = DateTime<Tz>> + 'a {
+    let tz = after.timezone();
+    let horizon = after.naive_local().year().max(self.dtstart.year()) + MAX_YEARS_AHEAD;
+    let mut period = first_period;
+    let mut empty_periods = 0;
+    let mut candidates = Vec::new().into_iter();
+    std::iter::from_fn(move || loop {
+      let Some(candidate) = candidates.next() else {
+        if empty_periods >= MAX_PERIODS {
+          return None;
+        }
+        let start = self.period_start(period)?;
+        if start.year() > horizon {
+          return None;
+        }
+        period += 1;
+        let set: Vec<NaiveDateTime> = self.occurrences_in_period(start).into_iter()
+          .filter(|candidate| *candidate >= self.dtstart)
+          .collect();
+        empty_periods = if set.is_empty() { empty_periods + 1 } else { 0 };
+        candidates = set.into_iter();
+        continue;
+      };
+      let occurrence = from_local(&tz, &candidate);
+      let past_until = match (&self.until, &occurrence) {
+        (Some(Until::Floating(until)), _) => candidate > *until,
+        (Some(Until::Utc(until)), Some(occurrence)) => occurrence.with_timezone(&Utc) > *until,
+        _ => false,
+      };
+      if past_until {
+        return None;
+      }
+      if let Some(occurrence) = occurrence {
+        return Some(occurrence);
+      }
+    })
+    .fuse()
+  }
+
+  fn excludes<Tz: TimeZone>(&self, occurrence: &DateTime<Tz>) -> bool {
+    self.exdates.contains(&occurrence.with_timezone(&Utc))
+  }
+
+  /// Index of the period (in units of `INTERVAL`) that holds `at`, or 0 if `at` precedes `DTSTART`.
+  fn period_index(&self, at: &NaiveDateTime) -> i64 {
+    if *at <= self.dtstart {
+      return 0;
+    }
+    let units = match self.freq {
+      Frequency::Yearly => (at.year() - self.dtstart.year()) as i64,
+      Frequency::Monthly => {
+        (at.year() - self.dtstart.year()) as i64 * 12 + at.month() as i64 - self.dtstart.month() as i64
+      }
+      Frequency::Weekly => {
+        (self.week_start_of(at.date()) - self.week_start_of(self.dtstart.date())).num_days() / 7
+      }
+      Frequency::Daily => (at.date() - self.dtstart.date()).num_days(),
+      Frequency::Hourly => (*at - self.dtstart).num_hours(),
+      Frequency::Minutely => (*at - self.dtstart).num_minutes(),
+      Frequency::Secondly => (*at - self.dtstart).num_seconds(),
+    };
+    units.max(0) / self.interval as i64
+  }
+
+  fn period_start(&self, period: i64) -> Option<NaiveDateTime> {
+    let steps = period.checked_mul(self.interval as i64)?;
+    let dtstart = self.dtstart;
+    let midnight = |date: NaiveDate| date.and_time(NaiveTime::MIN);
+    match self.freq {
+      Frequency::Yearly => NaiveDate::from_ymd_opt(dtstart.year() + i32::try_from(steps).ok()?, 1, 1).map(midnight),
+      Frequency::Monthly => dtstart
+        .date()
+        .with_day(1)?
+        .checked_add_months(Months::new(u32::try_from(steps).ok()?))
+        .map(midnight),
+      Frequency::Weekly => self
+        .week_start_of(dtstart.date())
+        .checked_add_signed(Duration::try_weeks(steps)?)
+        .map(midnight),
+      Frequency::Daily => dtstart.date().checked_add_signed(Duration::try_days(steps)?).map(midnight),
+      Frequency::Hourly => dtstart.with_minute(0)?.with_second(0)?.checked_add_signed(Duration::try_hours(steps)?),
+      Frequency::Minutely => dtstart.with_second(0)?.checked_add_signed(Duration::try_minutes(steps)?),
+      Frequency::Secondly => dtstart.checked_add_signed(Duration::try_seconds(steps)?),
+    }
+  }
+
+  /// Sorted occurrences of the period starting at `start`, after applying `BYSETPOS`.
+  fn occurrences_in_period(&self, start: NaiveDateTime) -> Vec<NaiveDateTime> {
+    let mut set = match self.freq {
+      Frequency::Yearly | Frequency::Monthly | Frequency::Weekly | Frequency::Daily => {
+        let first_day = start.date();
+        let last_day = match self.freq {
+          Frequency::Yearly => NaiveDate::from_ymd_opt(first_day.year(), 12, 31),
+          Frequency::Monthly => first_day.checked_add_months(Months::new(1)).and_then(|d| d.pred_opt()),
+          Frequency::Weekly => first_day.checked_add_signed(Duration::days(6)),
+          _ => Some(first_day),
+        };
+        let Some(last_day) = last_day else {
+          return Vec::new();
+        };
+        let times = self.times(&self.by_hour, self.dtstart.hour(), &self.by_minute, self.dtstart.minute());
+        first_day
+          .iter_days()
+          .take_while(|day| *day <= last_day)
+          .filter(|day| self.matches_date(*day))
+          .flat_map(|day| times.iter().map(move |time| day.and_time(*time)))
+          .collect::<Vec<_>>()
+      }
+      Frequency::Hourly => {
+        if !self.matches_date(start.date()) || !matches_unsigned(&self.by_hour, start.hour()) {
+          return Vec::new();
+        }
+        self
+          .times(&[start.hour()], start.hour(), &self.by_minute, self.dtstart.minute())
+          .into_iter()
+          .map(|time| start.date().and_time(time))
+          .collect()
+      }
+      Frequency::Minutely => {
+        if !self.matches_date(start.date())
+          || !matches_unsigned(&self.by_hour, start.hour())
+          || !matches_unsigned(&self.by_minute, start.minute())
+        {
+          return Vec::new();
+        }
+        self
+          .times(&[start.hour()], start.hour(), &[start.minute()], start.minute())
+          .into_iter()
+          .map(|time| start.date().and_time(time))
+          .collect()
+      }
+      Frequency::Secondly => {
+        let matches = self.matches_date(start.date())
+          && matches_unsigned(&self.by_hour, start.hour())
+          && matches_unsigned(&self.by_minute, start.minute())
+          && matches_unsigned(&self.by_second, start.second());
+        if matches {
+          vec![start]
+        } else {
+          Vec::new()
+        }
+      }
+    };
+    set.sort();
+    set.dedup();
+
+    if self.by_set_pos.is_empty() {
+      return set;
+    }
+    let mut selected: Vec<NaiveDateTime> = self
+      .by_set_pos
+      .iter()
+      .filter_map(|pos| {
+        let index = if *pos > 0 { *pos as usize - 1 } else { set.len().checked_sub(pos.unsigned_abs() as usize)? };
+        set.get(index).copied()
+      })
+      .collect();
+    selected.sort();
+    selected.dedup();
+    selected
+  }
+
+  /// Times of day combining `BYHOUR`, `BYMINUTE` and `BYSECOND`, defaulting to `DTSTART`'s.
+  fn times(&self, hours: &[u32], default_hour: u32, minutes: &[u32], default_minute: u32) -> Vec<NaiveTime> {
+    let hours = if hours.is_empty() { vec![default_hour] } else { hours.to_vec() };
+    let minutes = if minutes.is_empty() { vec![default_minute] } else { minutes.to_vec() };
+    let seconds = if self.by_second.is_empty() { vec![self.dtstart.second()] } else { self.by_second.clone() };
+    let mut times = Vec::new();
+    for hour in &hours {
+      for minute in &minutes {
+        // leap seconds (BYSECOND=60) cannot be represented and are dropped
+        times.extend(seconds.iter().filter_map(|second| NaiveTime::from_hms_opt(*hour, *minute, *second)));
+      }
+    }
+    times
+  }
+
+  fn matches_date(&self, day: NaiveDate) -> bool {
+    if !matches_unsigned(&self.by_month, day.month()) {
+      return false;
+    }
+    if !self.by_week_no.is_empty() {
+      let (week, weeks_in_year) = self.week_number(day);
+      if !matches_signed(&self.by_week_no, week, weeks_in_year) {
+        return false;
+      }
+    }
+    if !self.by_year_day.is_empty() && !matches_signed(&self.by_year_day, day.ordinal(), days_in_year(day.year())) {
+      return false;
+    }
+    if !self.by_month_day.is_empty() && !matches_signed(&self.by_month_day, day.day(), days_in_month(day)) {
+      return false;
+    }
+    if !self.by_day.is_empty() && !self.by_day.iter().any(|(ordinal, weekday)| self.matches_weekday(day, *ordinal, *weekday)) {
+      return false;
+    }
+
+    // without any day-level BYxxx part, the day is taken from DTSTART
+    let no_day_rules = self.by_week_no.is_empty()
+      && self.by_year_day.is_empty()
+      && self.by_month_day.is_empty()
+      && self.by_day.is_empty();
+    match self.freq {
+      Frequency::Yearly if no_day_rules => {
+        (!self.by_month.is_empty() || day.month() == self.dtstart.month()) && day.day() == self.dtstart.day()
+      }
+      Frequency::Monthly if no_day_rules => day.day() == self.dtstart.day(),
+      Frequency::Weekly if self.by_day.is_empty() => day.weekday() == self.dtstart.weekday(),
+      _ => true,
+    }
+  }
+
+  fn matches_weekday(&self, day: NaiveDate, ordinal: Option<i32>, weekday: Weekday) -> bool {
+    if day.weekday() != weekday {
+      return false;
+    }
+    let Some(ordinal) = ordinal else {
+      return true;
+    };
+    // ordinals count within the month for MONTHLY rules, or YEARLY rules restricted by BYMONTH
+    let (position, days_in_scope) = if self.freq == Frequency::Monthly || !self.by_month.is_empty() {
+      (day.day(), days_in_month(day))
+    } else {
+      (day.ordinal(), days_in_year(day.year()))
+    };
+    if ordinal > 0 {
+      (position - 1) / 7 + 1 == ordinal as u32
+    } else {
+      (days_in_scope - position) / 7 + 1 == ordinal.unsigned_abs()
+    }
+  }
+
+  /// Week number of `day` (week 1 being the first week with at least four days in the year,
+  /// weeks starting on `WKST`) and the number of weeks in that year.
+  fn week_number(&self, day: NaiveDate) -> (u32, u32) {
+    let first_week = |year: i32| {
+      let jan_1 = NaiveDate::from_ymd_opt(year, 1, 1).unwrap_or(day);
+      let start = self.week_start_of(jan_1);
+      if (jan_1 - start).num_days() >= 4 {
+        start + Duration::weeks(1)
+      } else {
+        start
+      }
+    };
+    let mut year = day.year();
+    if day < first_week(year) {
+      year -= 1;
+    } else if day >= first_week(year + 1) {
+      year += 1;
+    }
+    let week = (day - first_week(year)).num_days() / 7 + 1;
+    let weeks_in_year = (first_week(year + 1) - first_week(year)).num_days() / 7;
+    (week as u32, weeks_in_year as u32)
+  }
+
+  fn week_start_of(&self, day: NaiveDate) -> NaiveDate {
+    let offset = (day.weekday().num_days_from_monday() + 7 - self.week_start.num_days_from_monday()) % 7;
+    day - Duration::days(offset as i64)
+  }
+}
+
+fn invalid(message: String) -> crate::Error {
+  crate::Error::Generic(format!("Invalid recurrence rule: {}", message))
+}
+
+fn parse_frequency(value: &str) -> crate::Result<Frequency> {
+  match value.to_ascii_uppercase().as_str() {
+    "YEARLY" => Ok(Frequency::Yearly),
+    "MONTHLY" => Ok(Frequency::Monthly),
+    "WEEKLY" => Ok(Frequency::Weekly),
+    "DAILY" => Ok(Frequency::Daily),
+    "HOURLY" => Ok(Frequency::Hourly),
+    "MINUTELY" => Ok(Frequency::Minutely),
+    "SECONDLY" => Ok(Frequency::Secondly),
+    _ => Err(invalid(format!("unknown FREQ '{}'", value))),
+  }
+}
+
+fn parse_until(value: &str) -> crate::Result<Until> {
+  let value = value.trim();
+  if let Some(utc) = value.strip_suffix('Z') {
+    return NaiveDateTime::parse_from_str(utc, "%Y%m%dT%H%M%S")
+      .map(|until| Until::Utc(until.and_utc()))
+      .map_err(|_| invalid(format!("invalid UNTIL '{}'", value)));
+  }
+  NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%S")
+    .or_else(|_| {
+      // a date-only UNTIL includes the whole day
+      NaiveDate::parse_from_str(value, "%Y%m%d").map(|date| date.and_time(NaiveTime::MIN) + Duration::days(1) - Duration::seconds(1))
+    })
+    .map(Until::Floating)
+    .map_err(|_| invalid(format!("invalid UNTIL '{}'", value)))
+}
+
+fn parse_list(name: &str, value: &str, min: u32, max: u32) -> crate::Result<Vec<u32>> {
+  value
+    .split(',')
+    .map(|item| match item.trim().parse::<u32>() {
+      Ok(number) if (min..=max).contains(&number) => Ok(number),
+      _ => Err(invalid(format!("invalid {} value '{}'", name, item))),
+    })
+    .collect()
+}
+
+fn parse_signed_list(name: &str, value: &str, max: u32) -> crate::Result<Vec<i32>> {
+  value
+    .split(',')
+    .map(|item| match item.trim().trim_start_matches('+').parse::<i32>() {
+      Ok(number) if number != 0 && number.unsigned_abs() <= max => Ok(number),
+      _ => Err(invalid(format!("invalid {} value '{}'", name, item))),
+    })
+    .collect()
+}
+
+fn parse_weekday_num(value: &str) -> crate::Result<(Option<i32>, Weekday)> {
+  let value = value.trim();
+  // the weekday is the last two characters, which are not bytes for a non-ASCII value
+  let split = value.char_indices().rev().nth(1).map_or(0, |(index, _)| index);
+  let (ordinal, weekday) = value.split_at(split);
+  let weekday = parse_weekday(weekday)?;
+  if ordinal.is_empty() {
+    return Ok((None, weekday));
+  }
+  match ordinal.trim_start_matches('+').parse::<i32>() {
+    Ok(ordinal) if ordinal != 0 && ordinal.abs() <= 53 => Ok((Some(ordinal), weekday)),
+    _ => Err(invalid(format!("invalid BYDAY value '{}'", value))),
+  }
+}
+
+fn parse_weekday(value: &str) -> crate::Result<Weekday> {
+  match value.to_ascii_uppercase().as_str() {
+    "MO" => Ok(Weekday::Mon),
+    "TU" => Ok(Weekday::Tue),
+    "WE" => Ok(Weekday::Wed),
+    "TH" => Ok(Weekday::Thu),
+    "FR" => Ok(Weekday::Fri),
+    "SA" => Ok(Weekday::Sat),
+    "SU" => Ok(Weekday::Sun),
+    _ => Err(invalid(format!("invalid weekday '{}'", value))),
+  }
+}
+
+fn matches_unsigned(list: &[u32], value: u32) -> bool {
+  list.is_empty() || list.contains(&value)
+}
+
+fn matches_signed(list: &[i32], value: u32, len: u32) -> bool {
+  list.iter().any(|item| {
+    if *item > 0 {
+      *item as u32 == value
+    } else {
+      (len + 1).checked_sub(item.unsigned_abs()) == Some(value)
+    }
+  })
+}
+
+fn days_in_month(day: NaiveDate) -> u32 {
+  let first = day.with_day(1).unwrap_or(day);
+  first
+    .checked_add_months(Months::new(1))
+    .map(|next| (next - first).num_days() as u32)
+    .unwrap_or(31)
+}
+
+fn days_in_year(year: i32) -> u32 {
+  if NaiveDate::from_ymd_opt(year, 2, 29).is_some() {
+    366
+  } else {
+    365
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn dtstart() -> NaiveDateTime {
+    NaiveDate::from_ymd_opt(2026, 1, 6).unwrap().and_hms_opt(9, 0, 0).unwrap()
+  }
+
+  fn at(datetime: &str) -> NaiveDateTime {
+    NaiveDateTime::parse_from_str(datetime, "%Y-%m-%dT%H:%M:%S").unwrap()
+  }
+
+  /// Up to `limit` occurrences of a rule, from its `DTSTART` on, in UTC.
+  fn occurrences(rule: &RRule, limit: usize) -> Vec<String> {
+    let mut after = rule.start(&Utc) - Duration::seconds(1);
+    let mut occurrences = Vec::new();
+    while let Some(next) = rule.next_after(&after).filter(|_| occurrences.len() < limit) {
+      occurrences.push(next.format("%Y-%m-%dT%H:%M:%S").to_string());
+      after = next;
+    }
+    occurrences
+  }
+
+  fn expand(rule: &str, dtstart: &str, limit: usize) -> Vec<String> {
+    occurrences(&RRule::parse(rule, at(dtstart)).unwrap(), limit)
+  }
+
+  #[test]
+  fn expands_nth_weekdays_of_the_month() {
+    assert_eq!(expand("FREQ=MONTHLY;BYDAY=2TU", "2026-01-01T09:00:00", 4), [
+      "2026-01-13T09:00:00",
+      "2026-02-10T09:00:00",
+      "2026-03-10T09:00:00",
+      "2026-04-14T09:00:00",
+    ]);
+    assert_eq!(expand("FREQ=MONTHLY;BYDAY=-1FR", "2026-01-01T09:00:00", 2), ["2026-01-30T09:00:00", "2026-02-27T09:00:00"]);
+  }
+
+  #[test]
+  fn picks_the_last_weekday_with_bysetpos() {
+    assert_eq!(expand("FREQ=MONTHLY;INTERVAL=3;BYDAY=MO,TU,WE,TH,FR;BYSETPOS=-1", "2026-03-01T18:00:00", 4), [
+      "2026-03-31T18:00:00",
+      "2026-06-30T18:00:00",
+      "2026-09-30T18:00:00",
+      "2026-12-31T18:00:00",
+    ]);
+    // May 2026 ends on a Sunday
+    assert_eq!(expand("FREQ=MONTHLY;BYDAY=MO,TU,WE,TH,FR;BYSETPOS=-1", "2026-05-01T18:00:00", 1), ["2026-05-29T18:00:00"]);
+  }
+
+  #[test]
+  fn skips_exdates() {
+    let exdate = Utc.with_ymd_and_hms(2026, 1, 8, 9, 0, 0).unwrap();
+    let rule = RRule::parse("FREQ=DAILY;COUNT=5", dtstart()).unwrap().with_exdates(vec![exdate]);
+    // excluded occurrences still count towards COUNT
+    assert_eq!(occurrences(&rule, 10), [
+      "2026-01-06T09:00:00",
+      "2026-01-07T09:00:00",
+      "2026-01-09T09:00:00",
+      "2026-01-10T09:00:00",
+    ]);
+  }
+
+  #[test]
+  fn stops_at_count() {
+    assert_eq!(expand("FREQ=WEEKLY;COUNT=3", "2026-01-06T09:00:00", 10), [
+      "2026-01-06T09:00:00",
+      "2026-01-13T09:00:00",
+      "2026-01-20T09:00:00",
+    ]);
+    let rule = RRule::parse("FREQ=WEEKLY;COUNT=3", dtstart()).unwrap();
+    assert_eq!(rule.next_after(&Utc.with_ymd_and_hms(2026, 1, 10, 0, 0, 0).unwrap()).unwrap().day(), 13);
+    assert!(rule.next_after(&Utc.with_ymd_and_hms(2026, 1, 20, 9, 0, 0).unwrap()).is_none());
+  }
+
+  #[test]
+  fn counts_occurrences_from_a_past_dtstart() {
+    let rule = RRule::parse("FREQ=DAILY;COUNT=5", dtstart()).unwrap();
+    // three of the five occurrences are past, only the last two are left
+    let mut after = Utc.with_ymd_and_hms(2026, 1, 8, 12, 0, 0).unwrap();
+    let mut left = Vec::new();
+    while let Some(next) = rule.next_after(&after) {
+      left.push(next.format("%Y-%m-%dT%H:%M:%S").to_string());
+      after = next;
+    }
+    assert_eq!(left, ["2026-01-09T09:00:00", "2026-01-10T09:00:00"]);
+
+    // more than 500 000 minutes, the most periods walked without an occurrence, from DTSTART
+    let rule = RRule::parse("FREQ=MINUTELY;COUNT=600000", at("2025-01-01T00:00:00")).unwrap();
+    let after = Utc.with_ymd_and_hms(2026, 1, 10, 12, 0, 30).unwrap();
+    assert_eq!(rule.next_after(&after).unwrap(), Utc.with_ymd_and_hms(2026, 1, 10, 12, 1, 0).unwrap());
+    // the 600 000th minute falls on 2026-02-21 15:59
+    assert_eq!(rule.next_after(&Utc.with_ymd_and_hms(2026, 2, 21, 15, 58, 0).unwrap()).unwrap(), Utc.with_ymd_and_hms(2026, 2, 21, 15, 59, 0).unwrap());
+    assert!(rule.next_after(&Utc.with_ymd_and_hms(2026, 2, 21, 15, 59, 0).unwrap()).is_none());
+  }
+
+  #[test]
+  fn stops_at_until() {
+    assert_eq!(expand("FREQ=DAILY;UNTIL=20260108T090000Z", "2026-01-06T09:00:00", 10), [
+      "2026-01-06T09:00:00",
+      "2026-01-07T09:00:00",
+      "2026-01-08T09:00:00",
+    ]);
+    assert_eq!(expand("FREQ=DAILY;UNTIL=20260107T120000", "2026-01-06T09:00:00", 10), ["2026-01-06T09:00:00", "2026-01-07T09:00:00"]);
+    // a date-only UNTIL includes the whole day
+    assert_eq!(expand("FREQ=DAILY;UNTIL=20260107", "2026-01-06T09:00:00", 10), ["2026-01-06T09:00:00", "2026-01-07T09:00:00"]);
+    assert!(RRule::parse("FREQ=DAILY;COUNT=2;UNTIL=20260108", dtstart()).is_err());
+  }
+
+  #[test]
+  fn expands_week_numbers() {
+    // RFC 5545: Monday of week number 20
+    assert_eq!(expand("FREQ=YEARLY;BYWEEKNO=20;BYDAY=MO", "1997-05-12T09:00:00", 3), [
+      "1997-05-12T09:00:00",
+      "1998-05-11T09:00:00",
+      "1999-05-17T09:00:00",
+    ]);
+    // week 1 of 2027 starts on January 4th, the one of 2028 on January 3rd
+    assert_eq!(expand("FREQ=YEARLY;BYWEEKNO=1;BYDAY=MO", "2026-01-01T09:00:00", 2), ["2027-01-04T09:00:00", "2028-01-03T09:00:00"]);
+    assert!(RRule::parse("FREQ=MONTHLY;BYWEEKNO=1", dtstart()).is_err());
+  }
+
+  #[test]
+  fn starts_weeks_on_wkst() {
+    // RFC 5545: the same rule with weeks starting on Monday and on Sunday
+    assert_eq!(expand("FREQ=WEEKLY;INTERVAL=2;COUNT=4;BYDAY=TU,SU;WKST=MO", "1997-08-05T09:00:00", 10), [
+      "1997-08-05T09:00:00",
+      "1997-08-10T09:00:00",
+      "1997-08-19T09:00:00",
+      "1997-08-24T09:00:00",
+    ]);
+    assert_eq!(expand("FREQ=WEEKLY;INTERVAL=2;COUNT=4;BYDAY=TU,SU;WKST=SU", "1997-08-05T09:00:00", 10), [
+      "1997-08-05T09:00:00",
+      "1997-08-17T09:00:00",
+      "1997-08-19T09:00:00",
+      "1997-08-31T09:00:00",
+    ]);
+  }
+
+  #[test]
+  fn rejects_non_ascii_weekdays() {
+    assert!(RRule::parse("FREQ=WEEKLY;BYDAY=éT", dtstart()).is_err());
+    assert!(RRule::parse("FREQ=MONTHLY;BYDAY=2é", dtstart()).is_err());
+    assert!(RRule::parse("FREQ=WEEKLY;BYDAY=é", dtstart()).is_err());
+  }
+}
@@ -0,0 +1,223 @@
+//! Storage backends for the scheduled tasks.
+//!
+//! The desktop implementation keeps every [`TaskInfo`] in a [`TaskStore`], which decides where the
+//! tasks live between runs of the app. The JSON file store is used by default; a custom store can be
+//! handed to [`crate::init_with_handler_and_store`].
+
+use chrono::{DateTime, Utc};
+
+use crate::models::{TaskInfo, TaskRun};
+
+#[cfg(feature = "json-store")]
+mod json;
+#[cfg(feature = "memory-store")]
+mod memory;
+#[cfg(feature = "sqlite-store")]
+mod sqlite;
+
+#[cfg(feature = "json-store")]
+pub use json::JsonFileStore;
+#[cfg(feature = "memory-store")]
+pub use memory::MemoryStore;
+#[cfg(feature = "sqlite-store")]
+pub use sqlite::SqliteStore;
+
+/// Where the scheduled tasks are kept.
+///
+/// Implementations must be safe to call from several threads: task timers update their task
+/// while commands list, add or cancel others.
+pub trait TaskStore: Send + Sync {
+  /// Returns the task with the given id, if any.
+  fn get(&self, task_id: &str) -> crate::Result<Option<TaskInfo>>;
+
+  /// Returns every stored task, in no particular order.
+  fn list(&self) -> crate::Result<Vec<TaskInfo>>;
+
+  /// Returns the tasks that will never run again, in no particular order.
+  ///
+  /// Stores able to look tasks up by status should override the default, which lists every task.
+  fn list_finished(&self) -> crate::Result<Vec<TaskInfo>> {
+    Ok(self.list()?.into_iter().filter(|task| task.status.is_finished()).collect())
+  }
+
+  /// Inserts a task, or replaces the stored task with the same id.
+  fn save(&self, task: &TaskInfo) -> crate::Result<()>;
+
+  /// Applies `update` to a stored task and saves it, doing nothing when it is not stored.
+  ///
+  /// Stores shared with other processes, such as those an OS scheduler launches to run the tasks,
+  /// should override the default, which reads then saves the task, so that no change made in
+  /// between is lost.
+  fn update(&self, task_id: &str, update: &mut dyn FnMut(&mut TaskInfo)) -> crate::Result<()> {
+    if let Some(mut task) = self.get(task_id)? {
+      update(&mut task);
+      self.save(&task)?;
+    }
+    Ok(())
+  }
+
+  /// Removes a task, doing nothing when it is not stored.
+  fn remove(&self, task_id: &str) -> crate::Result<()>;
+
+  /// Appends a finished run to the history of its task.
+  ///
+  /// The history is optional: stores that keep none can rely on the default methods.
+  fn add_run(&self, _run: &TaskRun) -> crate::Result<()> {
+    Ok(())
+  }
+
+  /// Returns a page of the history of a task, most recent run first, and the number of runs in it.
+  fn runs(&self, _task_id: &str, _offset: usize, _limit: Option<usize>) -> crate::Result<(Vec<TaskRun>, usize)> {
+    Ok((Vec::new(), 0))
+  }
+
+  /// Drops the runs of a task beyond the `max_runs` most recent ones, and those started before `oldest`.
+  fn prune_runs(&self, _task_id: &str, _max_runs: Option<usize>, _oldest: Option<DateTime<Utc>>) -> crate::Result<()> {
+    Ok(())
+  }
+}
+
+/// A page of a history kept oldest run first.
+#[cfg(any(feature = "json-store", feature = "memory-store"))]
+fn page(runs: &[TaskRun], offset: usize, limit: Option<usize>) -> (Vec<TaskRun>, usize) {
+  let page = runs.iter().rev().skip(offset).take(limit.unwrap_or(usize::MAX)).cloned().collect();
+  (page, runs.len())
+}
+
+/// Applies the retention limits to a history kept oldest run first.
+#[cfg(any(feature = "json-store", feature = "memory-store"))]
+fn prune(runs: &mut Vec<TaskRun>, max_runs: Option<usize>, oldest: Option<DateTime<Utc>>) {
+  if let Some(oldest) = oldest {
+    // runs with an unreadable start are kept, the count limit still applies to them
+    runs.retain(|run| DateTime::parse_from_rfc3339(&run.started_at).map_or(true, |started_at| started_at >= oldest));
+  }
+  if let Some(max_runs) = max_runs {
+    let excess = runs.len().saturating_sub(max_runs);
+    runs.drain(..excess);
+  }
+}
+
+#[cfg(all(test, any(feature = "json-store", feature = "memory-store", feature = "sqlite-store")))]
+pub(crate) mod tests {
+  use super::*;
+
+  /// A task waiting for its first run.
+  pub(crate) fn task(task_id: &str) -> TaskInfo {
+    serde_json::from_value(serde_json::json!({
+      "taskId": task_id,
+      "taskName": "backup",
+      "scheduledTime": "2026-01-01T09:00:00+00:00",
+      "status": "scheduled",
+    }))
+    .unwrap()
+  }
+
+  /// A successful run of a task started `minutes` minutes after 2026-01-01T09:00:00Z.
+  pub(crate) fn run(task_id: &str, minutes: i64) -> TaskRun {
+    let started_at = DateTime::parse_from_rfc3339("2026-01-01T09:00:00+00:00").unwrap() + chrono::Duration::minutes(minutes);
+    TaskRun {
+      task_id: task_id.to_string(),
+      started_at: started_at.to_rfc3339(),
+      finished_at: (started_at + chrono::Duration::seconds(1)).to_rfc3339(),
+      duration_ms: 1000,
+      outcome: crate::models::RunOutcome::Succeeded,
+      error: None,
+      attempt: 1,
+      trigger: crate::models::RunTrigger::Schedule,
+    }
+  }
+
+  /// Checks that `store` gives back the tasks saved in it, and forgets the removed ones.
+  pub(crate) fn saves_gets_lists_and_removes_tasks(store: &dyn TaskStore) {
+    assert!(store.get("1").unwrap().is_none());
+    assert!(store.list().unwrap().is_empty());
+    store.save(&task("1")).unwrap();
+    store.save(&task("2")).unwrap();
+    let mut updated = task("1");
+    updated.run_count = 3;
+    updated.status = crate::models::TaskStatus::Completed;
+    store.save(&updated).unwrap();
+
+    let task_1 = store.get("1").unwrap().unwrap();
+    assert_eq!((task_1.run_count, task_1.status), (3, crate::models::TaskStatus::Completed));
+    let mut listed: Vec<String> = store.list().unwrap().into_iter().map(|task| task.task_id).collect();
+    listed.sort();
+    assert_eq!(listed, ["1", "2"]);
+
+    store.remove("1").unwrap();
+    store.remove("3").unwrap();
+    assert!(store.get("1").unwrap().is_none());
+    assert_eq!(store.list().unwrap().len(), 1);
+  }
+
+  /// Checks that `store` updates the tasks it holds in place, and only those.
+  pub(crate) fn updates_tasks_in_place(store: &dyn TaskStore) {
+    store.save(&task("1")).unwrap();
+    store.update("1", &mut |task| task.run_count += 1).unwrap();
+    store.update("1", &mut |task| task.run_count += 1).unwrap();
+    assert_eq!(store.get("1").unwrap().unwrap().run_count, 2);
+
+    let mut called = false;
+    store.update("2", &mut |_| called = true).unwrap();
+    assert!(!called);
+    assert!(store.get("2").unwrap().is_none());
+  }
+
+  /// Minutes after 2026-01-01T09:00:00Z the runs of a page started at.
+  fn started(runs: &[TaskRun]) -> Vec<i64> {
+    let start = DateTime::parse_from_rfc3339("2026-01-01T09:00:00+00:00").unwrap();
+    runs.iter().map(|run| (DateTime::parse_from_rfc3339(&run.started_at).unwrap() - start).num_minutes()).collect()
+  }
+
+  /// Checks that `store` pages the history of a task most recent run first.
+  pub(crate) fn pages_runs_most_recent_first(store: &dyn TaskStore) {
+    for minutes in 0..5 {
+      store.add_run(&run("1", minutes)).unwrap();
+    }
+    store.add_run(&run("2", 10)).unwrap();
+
+    let page = |offset, limit| {
+      let (runs, total) = store.runs("1", offset, limit).unwrap();
+      (started(&runs), total)
+    };
+    assert_eq!(page(0, Some(2)), (vec![4, 3], 5));
+    assert_eq!(page(2, Some(2)), (vec![2, 1], 5));
+    assert_eq!(page(4, Some(2)), (vec![0], 5));
+    assert_eq!(page(5, Some(2)), (vec![], 5));
+    assert_eq!(page(1, None), (vec![3, 2, 1, 0], 5));
+    assert_eq!(page(0, Some(0)), (vec![], 5));
+    assert_eq!(store.runs("3", 0, None).unwrap(), (vec![], 0));
+  }
+
+  /// Checks that `store` drops the runs of a task beyond `max_runs` and those older than `oldest`,
+  /// leaving other tasks alone.
+  pub(crate) fn prunes_runs_by_count_and_age(store: &dyn TaskStore) {
+    for minutes in 0..6 {
+      store.add_run(&run("1", minutes)).unwrap();
+    }
+    store.add_run(&run("2", 0)).unwrap();
+    let history = |task_id| started(&store.runs(task_id, 0, None).unwrap().0);
+
+    store.prune_runs("1", Some(4), None).unwrap();
+    assert_eq!(history("1"), [5, 4, 3, 2]);
+    store.prune_runs("1", Some(10), None).unwrap();
+    assert_eq!(history("1"), [5, 4, 3, 2]);
+    let oldest = DateTime::parse_from_rfc3339("2026-01-01T09:04:00+00:00").unwrap().with_timezone(&Utc);
+    store.prune_runs("1", None, Some(oldest)).unwrap();
+    assert_eq!(history("1"), [5, 4]);
+    store.prune_runs("1", Some(1), Some(oldest)).unwrap();
+    assert_eq!(history("1"), [5]);
+    store.prune_runs("1", Some(0), None).unwrap();
+    assert!(history("1").is_empty());
+    assert_eq!(history("2"), [0]);
+    store.prune_runs("3", Some(0), None).unwrap();
+  }
+
+  /// An empty directory under the temp dir, for the files of a store.
+  pub(crate) fn temp_dir(name: &str) -> std::path::PathBuf {
+    let dir = std::env::temp_dir().join(format!("schedule-task-store-{}-{}", std::process::id(), name));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+  }
+}
@@ -0,0 +1,257 @@
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use fs4::fs_std::FileExt;
+use serde::{de::DeserializeOwned, Serialize};
+
+use super::TaskStore;
+use crate::models::{TaskInfo, TaskRun};
+
+type Tasks = HashMap<String, TaskInfo>;
+type Runs = HashMap<String, Vec<TaskRun>>;
+
+/// Keeps the tasks in a JSON file, rewritten whole on every change.
+///
+/// The run history lives next to it, in a file of the same name ending in `.history.json`. Both
+/// files are read again on every call, under an advisory lock on a `.lock` file next to them, so
+/// that the app and the processes an OS scheduler launches to run its tasks see each other's changes.
+#[derive(Debug)]
+pub struct JsonFileStore {
+  path: PathBuf,
+  history_path: PathBuf,
+  lock_path: PathBuf,
+}
+
+impl JsonFileStore {
+  /// Opens the store at `path`, checking that the tasks already saved there can be read.
+  ///
+  /// The parent directories of the file are created, the file itself on the first change.
+  pub fn new(path: impl Into<PathBuf>) -> crate::Result<Self> {
+    let path = path.into();
+    let store = Self {
+      history_path: path.with_extension("history.json"),
+      lock_path: path.with_extension("lock"),
+      path,
+    };
+    store.read::<Tasks>(&store.path)?;
+    store.read::<Runs>(&store.history_path)?;
+    Ok(store)
+  }
+
+  pub fn path(&self) -> &Path {
+    &self.path
+  }
+
+  /// Locks the files of the store, shared or exclusively, until the returned file is dropped.
+  fn lock(&self, exclusive: bool) -> crate::Result<File> {
+    if let Some(dir) = self.lock_path.parent() {
+      std::fs::create_dir_all(dir)?;
+    }
+    let file = OpenOptions::new().create(true).truncate(false).write(true).open(&self.lock_path)?;
+    // called through the trait, as recent versions of std have inherent methods of the same names
+    if exclusive {
+      FileExt::lock_exclusive(&file)?;
+    } else {
+      FileExt::lock_shared(&file)?;
+    }
+    Ok(file)
+  }
+
+  /// Reads the content of one of the files of the store.
+  fn read<T: DeserializeOwned + Default>(&self, path: &Path) -> crate::Result<T> {
+    let _lock = self.lock(false)?;
+    load(path)
+  }
+
+  /// Applies `update` to the content of one of the files of the store, read under an exclusive
+  /// lock, and writes it back when `update` returns `true`.
+  fn rewrite<T: Serialize + DeserializeOwned + Default>(&self, path: &Path, update: impl FnOnce(&mut T) -> bool) -> crate::Result<()> {
+    let _lock = self.lock(true)?;
+    let mut content = load(path)?;
+    if update(&mut content) {
+      save(path, &content)?;
+    }
+    Ok(())
+  }
+}
+
+impl TaskStore for JsonFileStore {
+  fn get(&self, task_id: &str) -> crate::Result<Option<TaskInfo>> {
+    Ok(self.read::<Tasks>(&self.path)?.remove(task_id))
+  }
+
+  fn list(&self) -> crate::Result<Vec<TaskInfo>> {
+    Ok(self.read::<Tasks>(&self.path)?.into_values().collect())
+  }
+
+  fn save(&self, task: &TaskInfo) -> crate::Result<()> {
+    self.rewrite(&self.path, |tasks: &mut Tasks| {
+      tasks.insert(task.task_id.clone(), task.clone());
+      true
+    })
+  }
+
+  fn update(&self, task_id: &str, update: &mut dyn FnMut(&mut TaskInfo)) -> crate::Result<()> {
+    self.rewrite(&self.path, |tasks: &mut Tasks| match tasks.get_mut(task_id) {
+      Some(task) => {
+        update(task);
+        true
+      }
+      None => false,
+    })
+  }
+
+  fn remove(&self, task_id: &str) -> crate::Result<()> {
+    self.rewrite(&self.path, |tasks: &mut Tasks| tasks.remove(task_id).is_some())
+  }
+
+  fn add_run(&self, run: &TaskRun) -> crate::Result<()> {
+    self.rewrite(&self.history_path, |runs: &mut Runs| {
+      runs.entry(run.task_id.clone()).or_default().push(run.clone());
+      true
+    })
+  }
+
+  fn runs(&self, task_id: &str, offset: usize, limit: Option<usize>) -> crate::Result<(Vec<TaskRun>, usize)> {
+    let runs = self.read::<Runs>(&self.history_path)?;
+    Ok(super::page(runs.get(task_id).map_or(&[], Vec::as_slice), offset, limit))
+  }
+
+  fn prune_runs(&self, task_id: &str, max_runs: Option<usize>, oldest: Option<DateTime<Utc>>) -> crate::Result<()> {
+    self.rewrite(&self.history_path, |runs: &mut Runs| {
+      let Some(task_runs) = runs.get_mut(task_id) else {
+        return false;
+      };
+      let count = task_runs.len();
+      super::prune(task_runs, max_runs, oldest);
+      task_runs.len() < count
+    })
+  }
+}
+
+fn load<T: DeserializeOwned + Default>(path: &Path) -> crate::Result<T> {
+  match std::fs::read(path) {
+    Ok(content) => Ok(serde_json::from_slice(&content)?),
+    Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(T::default()),
+    Err(e) => Err(e.into()),
+  }
+}
+
+fn save<T: Serialize>(path: &Path, content: &T) -> crate::Result<()> {
+  // write then rename, so that a crash mid-write never leaves a truncated file behind
+  let tmp_path = path.with_extension("json.tmp");
+  std::fs::write(&tmp_path, serde_json::to_vec_pretty(content)?)?;
+  std::fs::rename(tmp_path, path)?;
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::store::tests::{run, task, temp_dir};
+
+  #[test]
+  fn saves_gets_lists_and_removes_tasks() {
+    let store = JsonFileStore::new(temp_dir("json-round-trip").join("tasks.json")).unwrap();
+    crate::store::tests::saves_gets_lists_and_removes_tasks(&store);
+  }
+
+  #[test]
+  fn updates_tasks_in_place() {
+    let store = JsonFileStore::new(temp_dir("json-update").join("tasks.json")).unwrap();
+    crate::store::tests::updates_tasks_in_place(&store);
+  }
+
+  #[test]
+  fn pages_runs_most_recent_first() {
+    let store = JsonFileStore::new(temp_dir("json-page").join("tasks.json")).unwrap();
+    crate::store::tests::pages_runs_most_recent_first(&store);
+  }
+
+  #[test]
+  fn prunes_runs_by_count_and_age() {
+    let store = JsonFileStore::new(temp_dir("json-prune").join("tasks.json")).unwrap();
+    crate::store::tests::prunes_runs_by_count_and_age(&store);
+  }
+
+  #[test]
+  fn reopens_the_files_it_wrote() {
+    let dir = temp_dir("json-reopen");
+    let path = dir.join("app").join("scheduled-tasks.json");
+    let store = JsonFileStore::new(&path).unwrap();
+    let mut saved = task("1");
+    saved.tags = vec!["nightly".to_string()];
+    store.save(&saved).unwrap();
+    store.add_run(&run("1", 0)).unwrap();
+    store.add_run(&run("1", 1)).unwrap();
+    drop(store);
+
+    assert!(path.exists());
+    assert!(dir.join("app").join("scheduled-tasks.history.json").exists());
+    let store = JsonFileStore::new(&path).unwrap();
+    assert_eq!(store.get("1").unwrap().unwrap().tags, ["nightly"]);
+    let (runs, total) = store.runs("1", 0, None).unwrap();
+    assert_eq!(total, 2);
+    assert_eq!(runs[0].started_at, run("1", 1).started_at);
+  }
+
+  #[test]
+  fn rejects_unreadable_files() {
+    let path = temp_dir("json-unreadable").join("tasks.json");
+    std::fs::write(&path, "not json").unwrap();
+    assert!(JsonFileStore::new(&path).is_err());
+  }
+
+  #[test]
+  fn shares_the_file_between_stores() {
+    let path = temp_dir("json-shared").join("tasks.json");
+    let app = JsonFileStore::new(&path).unwrap();
+    // e.g. a process launched by an OS scheduler to run the task
+    let launched = JsonFileStore::new(&path).unwrap();
+    app.save(&task("1")).unwrap();
+
+    let mut ran = launched.get("1").unwrap().unwrap();
+    ran.run_count = 1;
+    launched.save(&ran).unwrap();
+    launched.add_run(&run("1", 0)).unwrap();
+    app.save(&task("2")).unwrap();
+    app.add_run(&run("2", 1)).unwrap();
+
+    assert_eq!(app.get("1").unwrap().unwrap().run_count, 1);
+    assert_eq!(launched.list().unwrap().len(), 2);
+    assert_eq!(app.runs("1", 0, None).unwrap().1, 1);
+    assert_eq!(launched.runs("2", 0, None).unwrap().1, 1);
+  }
+
+  #[test]
+  fn updates_the_same_task_from_two_stores() {
+    let path = temp_dir("json-shared-update").join("tasks.json");
+    let app = JsonFileStore::new(&path).unwrap();
+    let launched = JsonFileStore::new(&path).unwrap();
+    app.save(&task("1")).unwrap();
+
+    // in turns, each store keeps the changes of the other
+    app.update("1", &mut |task| task.status = crate::models::TaskStatus::Running).unwrap();
+    launched.update("1", &mut |task| task.run_count += 1).unwrap();
+    app.update("1", &mut |task| task.error = Some("offline".to_string())).unwrap();
+    let task = launched.get("1").unwrap().unwrap();
+    assert_eq!(task.status, crate::models::TaskStatus::Running);
+    assert_eq!(task.run_count, 1);
+    assert_eq!(task.error.as_deref(), Some("offline"));
+
+    // and at the same time, no update is lost between the read and the write of the other
+    let threads: Vec<_> = [app, launched].into_iter()
+      .map(|store| std::thread::spawn(move || {
+        for _ in 0..25 {
+          store.update("1", &mut |task| task.run_count += 1).unwrap();
+        }
+      }))
+      .collect();
+    for thread in threads {
+      thread.join().unwrap();
+    }
+    assert_eq!(JsonFileStore::new(&path).unwrap().get("1").unwrap().unwrap().run_count, 51);
+  }
+}
@@ -0,0 +1,89 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use chrono::{DateTime, Utc};
+
+use super::TaskStore;
+use crate::models::{TaskInfo, TaskRun};
+
+/// Keeps the tasks in memory only, so they are lost when the app closes.
+#[derive(Debug, Default)]
+pub struct MemoryStore {
+  tasks: Mutex<HashMap<String, TaskInfo>>,
+  runs: Mutex<HashMap<String, Vec<TaskRun>>>,
+}
+
+impl MemoryStore {
+  pub fn new() -> Self {
+    Self::default()
+  }
+}
+
+impl TaskStore for MemoryStore {
+  fn get(&self, task_id: &str) -> crate::Result<Option<TaskInfo>> {
+    Ok(self.tasks.lock().unwrap().get(task_id).cloned())
+  }
+
+  fn list(&self) -> crate::Result<Vec<TaskInfo>> {
+    Ok(self.tasks.lock().unwrap().values().cloned().collect())
+  }
+
+  fn save(&self, task: &TaskInfo) -> crate::Result<()> {
+    self.tasks.lock().unwrap().insert(task.task_id.clone(), task.clone());
+    Ok(())
+  }
+
+  fn update(&self, task_id: &str, update: &mut dyn FnMut(&mut TaskInfo)) -> crate::Result<()> {
+    if let Some(task) = self.tasks.lock().unwrap().get_mut(task_id) {
+      update(task);
+    }
+    Ok(())
+  }
+
+  fn remove(&self, task_id: &str) -> crate::Result<()> {
+    self.tasks.lock().unwrap().remove(task_id);
+    Ok(())
+  }
+
+  fn add_run(&self, run: &TaskRun) -> crate::Result<()> {
+    self.runs.lock().unwrap().entry(run.task_id.clone()).or_default().push(run.clone());
+    Ok(())
+  }
+
+  fn runs(&self, task_id: &str, offset: usize, limit: Option<usize>) -> crate::Result<(Vec<TaskRun>, usize)> {
+    let runs = self.runs.lock().unwrap();
+    Ok(super::page(runs.get(task_id).map_or(&[], Vec::as_slice), offset, limit))
+  }
+
+  fn prune_runs(&self, task_id: &str, max_runs: Option<usize>, oldest: Option<DateTime<Utc>>) -> crate::Result<()> {
+    if let Some(runs) = self.runs.lock().unwrap().get_mut(task_id) {
+      super::prune(runs, max_runs, oldest);
+    }
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn saves_gets_lists_and_removes_tasks() {
+    crate::store::tests::saves_gets_lists_and_removes_tasks(&MemoryStore::new());
+  }
+
+  #[test]
+  fn updates_tasks_in_place() {
+    crate::store::tests::updates_tasks_in_place(&MemoryStore::new());
+  }
+
+  #[test]
+  fn pages_runs_most_recent_first() {
+    crate::store::tests::pages_runs_most_recent_first(&MemoryStore::new());
+  }
+
+  #[test]
+  fn prunes_runs_by_count_and_age() {
+    crate::store::tests::prunes_runs_by_count_and_age(&MemoryStore::new());
+  }
+}
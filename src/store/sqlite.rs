@@ -0,0 +1,227 @@
+use chrono::{DateTime, Utc};
+use rusqlite::{params, Connection, OptionalExtension, TransactionBehavior};
+use std::path::Path;
+use std::sync::Mutex;
+
+use super::TaskStore;
+use crate::models::{TaskInfo, TaskRun};
+
+/// Keeps the tasks in a SQLite database, one row per task, and their history one row per run.
+///
+/// Tasks and runs are stored as JSON alongside the columns they are looked up by, so that new
+/// fields never need a migration.
+#[derive(Debug)]
+pub struct SqliteStore {
+  connection: Mutex<Connection>,
+}
+
+impl SqliteStore {
+  /// Opens, or creates, the database at `path`.
+  pub fn open(path: impl AsRef<Path>) -> crate::Result<Self> {
+    let path = path.as_ref();
+    if let Some(dir) = path.parent() {
+      std::fs::create_dir_all(dir)?;
+    }
+    Self::from_connection(Connection::open(path)?)
+  }
+
+  /// Uses an already opened connection, creating the tables when missing.
+  pub fn from_connection(connection: Connection) -> crate::Result<Self> {
+    connection.execute_batch(
+      "CREATE TABLE IF NOT EXISTS scheduled_tasks (
+        task_id TEXT PRIMARY KEY NOT NULL,
+        task_name TEXT NOT NULL,
+        status TEXT NOT NULL,
+        task TEXT NOT NULL
+      );
+      CREATE INDEX IF NOT EXISTS scheduled_tasks_status ON scheduled_tasks (status);
+      CREATE TABLE IF NOT EXISTS task_runs (
+        run_id INTEGER PRIMARY KEY AUTOINCREMENT,
+        task_id TEXT NOT NULL,
+        started_at INTEGER NOT NULL,
+        run TEXT NOT NULL
+      );
+      CREATE INDEX IF NOT EXISTS task_runs_task_id ON task_runs (task_id, run_id)",
+    )?;
+    Ok(Self {
+      connection: Mutex::new(connection),
+    })
+  }
+}
+
+impl TaskStore for SqliteStore {
+  fn get(&self, task_id: &str) -> crate::Result<Option<TaskInfo>> {
+    get(&self.connection.lock().unwrap(), task_id)
+  }
+
+  fn list(&self) -> crate::Result<Vec<TaskInfo>> {
+    let connection = self.connection.lock().unwrap();
+    let mut statement = connection.prepare("SELECT task FROM scheduled_tasks")?;
+    let rows = statement.query_map([], |row| row.get::<_, String>(0))?;
+    let mut tasks = Vec::new();
+    for task in rows {
+      tasks.push(serde_json::from_str(&task?)?);
+    }
+    Ok(tasks)
+  }
+
+  fn list_finished(&self) -> crate::Result<Vec<TaskInfo>> {
+    let connection = self.connection.lock().unwrap();
+    let mut statement = connection.prepare(
+      "SELECT task FROM scheduled_tasks WHERE status IN ('completed', 'failed', 'cancelled', 'skipped')",
+    )?;
+    let rows = statement.query_map([], |row| row.get::<_, String>(0))?;
+    let mut tasks = Vec::new();
+    for task in rows {
+      tasks.push(serde_json::from_str(&task?)?);
+    }
+    Ok(tasks)
+  }
+
+  fn save(&self, task: &TaskInfo) -> crate::Result<()> {
+    save(&self.connection.lock().unwrap(), task)
+  }
+
+  fn update(&self, task_id: &str, update: &mut dyn FnMut(&mut TaskInfo)) -> crate::Result<()> {
+    let mut connection = self.connection.lock().unwrap();
+    // takes the write lock of the database up front, so that no other process changes the task in between
+    let transaction = connection.transaction_with_behavior(TransactionBehavior::Immediate)?;
+    if let Some(mut task) = get(&transaction, task_id)? {
+      update(&mut task);
+      save(&transaction, &task)?;
+    }
+    transaction.commit()?;
+    Ok(())
+  }
+
+  fn remove(&self, task_id: &str) -> crate::Result<()> {
+    self.connection.lock().unwrap().execute("DELETE FROM scheduled_tasks WHERE task_id = ?1", params![task_id])?;
+    Ok(())
+  }
+
+  fn add_run(&self, run: &TaskRun) -> crate::Result<()> {
+    // runs are pruned by start, kept as milliseconds since the epoch to compare them in SQL
+    let started_at = DateTime::parse_from_rfc3339(&run.started_at).map_or(0, |started_at| started_at.timestamp_millis());
+    self.connection.lock().unwrap().execute(
+      "INSERT INTO task_runs (task_id, started_at, run) VALUES (?1, ?2, ?3)",
+      params![run.task_id, started_at, serde_json::to_string(run)?],
+    )?;
+    Ok(())
+  }
+
+  fn runs(&self, task_id: &str, offset: usize, limit: Option<usize>) -> crate::Result<(Vec<TaskRun>, usize)> {
+    let connection = self.connection.lock().unwrap();
+    let total: i64 = connection.query_row("SELECT COUNT(*) FROM task_runs WHERE task_id = ?1", params![task_id], |row| row.get(0))?;
+    let mut statement = connection.prepare(
+      "SELECT run FROM task_runs WHERE task_id = ?1 ORDER BY run_id DESC LIMIT ?2 OFFSET ?3",
+    )?;
+    // a negative limit means no limit to SQLite
+    let limit = limit.map_or(-1, |limit| i64::try_from(limit).unwrap_or(i64::MAX));
+    let offset = i64::try_from(offset).unwrap_or(i64::MAX);
+    let rows = statement.query_map(params![task_id, limit, offset], |row| row.get::<_, String>(0))?;
+    let mut runs = Vec::new();
+    for run in rows {
+      runs.push(serde_json::from_str(&run?)?);
+    }
+    Ok((runs, total as usize))
+  }
+
+  fn prune_runs(&self, task_id: &str, max_runs: Option<usize>, oldest: Option<DateTime<Utc>>) -> crate::Result<()> {
+    let connection = self.connection.lock().unwrap();
+    if let Some(oldest) = oldest {
+      connection.execute(
+        "DELETE FROM task_runs WHERE task_id = ?1 AND started_at < ?2",
+        params![task_id, oldest.timestamp_millis()],
+      )?;
+    }
+    if let Some(max_runs) = max_runs {
+      connection.execute(
+        "DELETE FROM task_runs WHERE task_id = ?1 AND run_id NOT IN
+          (SELECT run_id FROM task_runs WHERE task_id = ?1 ORDER BY run_id DESC LIMIT ?2)",
+        params![task_id, i64::try_from(max_runs).unwrap_or(i64::MAX)],
+      )?;
+    }
+    Ok(())
+  }
+}
+
+fn get(connection: &Connection, task_id: &str) -> crate::Result<Option<TaskInfo>> {
+  let task: Option<String> = connection
+    .query_row("SELECT task FROM scheduled_tasks WHERE task_id = ?1", params![task_id], |row| row.get(0))
+    .optional()?;
+  Ok(task.map(|task| serde_json::from_str(&task)).transpose()?)
+}
+
+fn save(connection: &Connection, task: &TaskInfo) -> crate::Result<()> {
+  let status = serde_json::to_value(&task.status)?;
+  connection.execute(
+    "INSERT INTO scheduled_tasks (task_id, task_name, status, task) VALUES (?1, ?2, ?3, ?4)
+      ON CONFLICT(task_id) DO UPDATE SET task_name = excluded.task_name, status = excluded.status, task = excluded.task",
+    params![task.task_id, task.task_name, status.as_str().unwrap_or_default(), serde_json::to_string(task)?],
+  )?;
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::models::TaskStatus;
+  use crate::store::tests::{run, task, temp_dir};
+
+  fn store(name: &str) -> SqliteStore {
+    SqliteStore::open(temp_dir(name).join("tasks.sqlite")).unwrap()
+  }
+
+  #[test]
+  fn saves_gets_lists_and_removes_tasks() {
+    crate::store::tests::saves_gets_lists_and_removes_tasks(&store("sqlite-round-trip"));
+  }
+
+  #[test]
+  fn updates_tasks_in_place() {
+    crate::store::tests::updates_tasks_in_place(&store("sqlite-update"));
+  }
+
+  #[test]
+  fn pages_runs_most_recent_first() {
+    crate::store::tests::pages_runs_most_recent_first(&store("sqlite-pages"));
+  }
+
+  #[test]
+  fn prunes_runs_by_count_and_age() {
+    crate::store::tests::prunes_runs_by_count_and_age(&store("sqlite-prune"));
+  }
+
+  #[test]
+  fn reopens_the_database_it_wrote() {
+    let path = temp_dir("sqlite-reopen").join("tasks.sqlite");
+    let store = SqliteStore::open(&path).unwrap();
+    store.save(&task("1")).unwrap();
+    store.add_run(&run("1", 0)).unwrap();
+    drop(store);
+
+    let store = SqliteStore::open(&path).unwrap();
+    assert_eq!(store.get("1").unwrap().unwrap().task_name, "backup");
+    assert_eq!(store.runs("1", 0, None).unwrap().1, 1);
+  }
+
+  #[test]
+  fn lists_finished_tasks_by_status() {
+    let store = store("sqlite-finished");
+    for (task_id, status) in [
+      ("scheduled", TaskStatus::Scheduled),
+      ("completed", TaskStatus::Completed),
+      ("paused", TaskStatus::Paused),
+      ("failed", TaskStatus::Failed),
+      ("cancelled", TaskStatus::Cancelled),
+      ("skipped", TaskStatus::Skipped),
+    ] {
+      let mut task = task(task_id);
+      task.status = status;
+      store.save(&task).unwrap();
+    }
+    let mut finished: Vec<String> = store.list_finished().unwrap().into_iter().map(|task| task.task_id).collect();
+    finished.sort();
+    assert_eq!(finished, ["cancelled", "completed", "failed", "skipped"]);
+  }
+}
@@ -0,0 +1,174 @@
+//! OS schedulers that launch the app to run tasks while it is closed.
+//!
+//! A task handed to a [`SystemScheduler`] is not timed by the app: the OS starts the executable
+//! with `--task-id=<id>`, `--run-task=<name>` and one `--task-param=<key>=<value>` per parameter,
+//! which [`crate::init_with_handler`] turns into a run of the task recorded in the store, as the
+//! runs timed by the app are.
+
+use chrono::Local;
+use std::path::PathBuf;
+
+use crate::models::{MisfirePolicy, ScheduleTime, TaskInfo, TaskParameters, TaskStatus};
+use crate::recurrence::Recurrence;
+
+mod crontab;
+mod launchd;
+mod systemd;
+mod task_scheduler;
+
+pub use crontab::CrontabScheduler;
+pub use launchd::LaunchdScheduler;
+pub use systemd::SystemdScheduler;
+pub use task_scheduler::{render_task_xml, WindowsTaskScheduler};
+
+/// An OS scheduler that tasks can be installed in.
+pub trait SystemScheduler: Send + Sync {
+  /// Short name of the scheduler, recorded in [`TaskInfo::system_scheduler`].
+  fn name(&self) -> &'static str;
+
+  /// Whether the scheduler can run a task on `schedule`.
+  fn supports(&self, schedule: &ScheduleTime) -> bool;
+
+  /// Installs a task, replacing any previous installation of the same task id.
+  fn install(&self, task: &TaskInfo, command: &TaskCommand) -> crate::Result<()>;
+
+  /// Removes a task, doing nothing when it is not installed.
+  fn uninstall(&self, task_id: &str) -> crate::Result<()>;
+
+  /// Returns the tasks installed by this app.
+  fn list(&self) -> crate::Result<Vec<TaskInfo>>;
+}
+
+/// The command line an OS scheduler runs to execute a task.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TaskCommand {
+  pub program: PathBuf,
+  pub args: Vec<String>,
+}
+
+impl TaskCommand {
+  /// Launches `program` to run `task`, with its parameters sorted by key.
+  pub fn new(program: impl Into<PathBuf>, task: &TaskInfo) -> Self {
+    let parameters = task.parameters.as_ref().map(TaskParameters::to_strings).unwrap_or_default();
+    let mut args = vec![format!("--task-id={}", task.task_id), format!("--run-task={}", task.task_name)];
+    args.extend(parameters.into_iter().map(|(key, value)| format!("--task-param={}={}", key, value)));
+    Self {
+      program: program.into(),
+      args,
+    }
+  }
+
+  /// Launches the running executable.
+  pub fn current_exe(task: &TaskInfo) -> crate::Result<Self> {
+    Ok(Self::new(std::env::current_exe()?, task))
+  }
+}
+
+/// Fails when the name or the command line of a task holds a control character.
+///
+/// OS schedulers keep tasks in line-based files, where a newline would end the entry and let the
+/// rest of a task name or parameter be read as entries, or directives, of its own.
+fn check_command(task: &TaskInfo, command: &TaskCommand) -> crate::Result<()> {
+  let program = command.program.to_string_lossy();
+  let invalid = std::iter::once(task.task_name.as_str())
+    .chain(std::iter::once(program.as_ref()))
+    .chain(command.args.iter().map(String::as_str))
+    .find(|value| value.chars().any(char::is_control));
+  match invalid {
+    Some(value) => Err(crate::Error::Generic(format!(
+      "Task {} cannot be installed in an OS scheduler, {:?} holds a control character",
+      task.task_id, value
+    ))),
+    None => Ok(()),
+  }
+}
+
+/// Rebuilds the [`TaskInfo`] of a task installed in an OS scheduler from its schedule and the
+/// arguments it launches the app with.
+fn installed_task<S: AsRef<str>>(task_id: &str, schedule: ScheduleTime, args: &[S], system_scheduler: &str) -> Option<TaskInfo> {
+  let (task_name, parameters) = crate::parse_scheduled_task_args(args)?;
+  let next_run = Recurrence::from_schedule(&schedule, None).ok()
+    .and_then(|recurrence| recurrence.first_run(&Local::now()));
+  let mut task = TaskInfo {
+    task_id: task_id.to_string(),
+    task_name,
+    scheduled_time: String::new(),
+    scheduled_time_utc: String::new(),
+    status: TaskStatus::Scheduled,
+    parameters: Some(parameters),
+    run_count: 0,
+    remaining_runs: None,
+    schedule: Some(schedule),
+    misfire_policy: MisfirePolicy::default(),
+    catch_up: false,
+    system_scheduler: Some(system_scheduler.to_string()),
+    error: None,
+    finished_at: None,
+    retry_policy: None,
+    attempt: 0,
+    next_retry_at: None,
+    timeout: None,
+    tags: Vec::new(),
+    timezone: None,
+  };
+  if let Some(next_run) = next_run {
+    task.set_scheduled_time(&next_run);
+  }
+  Some(task)
+}
+
+/// Cron month names, from 1 (January).
+const MONTHS: [&str; 12] = ["JAN", "FEB", "MAR", "APR", "MAY", "JUN", "JUL", "AUG", "SEP", "OCT", "NOV", "DEC"];
+/// Cron weekday names, from 0 (Sunday).
+const WEEKDAYS: [&str; 7] = ["SUN", "MON", "TUE", "WED", "THU", "FRI", "SAT"];
+
+/// The sorted values matched by a cron field.
+fn expand_cron_field(field: &str, min: u32, max: u32, names: &[&str]) -> Option<Vec<u32>> {
+  let value = |part: &str| -> Option<u32> {
+    let value = match names.iter().position(|name| name.eq_ignore_ascii_case(part)) {
+      // names are listed from the first value of the field
+      Some(index) => index as u32 + min,
+      None => part.parse().ok()?,
+    };
+    (min..=max).contains(&value).then_some(value)
+  };
+  let mut values = Vec::new();
+  for part in field.split(',') {
+    let (range, step) = match part.split_once('/') {
+      Some((range, step)) => (range, step.parse::<u32>().ok().filter(|step| *step > 0)?),
+      None => (part, 1),
+    };
+    let (start, end) = match range {
+      "*" | "?" => (min, max),
+      range => match range.split_once('-') {
+        Some((start, end)) => (value(start)?, value(end)?),
+        // `a/n` runs from `a` to the end of the range
+        None if part.contains('/') => (value(range)?, max),
+        None => (value(range)?, value(range)?),
+      },
+    };
+    if start > end {
+      return None;
+    }
+    values.extend((start..=end).step_by(step as usize));
+  }
+  values.sort_unstable();
+  values.dedup();
+  Some(values)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn commands_carry_the_task_id() {
+    let task = installed_task("42", ScheduleTime::Cron("0 9 * * *".to_string()), &["--run-task=backup", "--task-param=target=nas"], "crontab").unwrap();
+    let command = TaskCommand::new("/usr/bin/app", &task);
+    assert_eq!(command.args, ["--task-id=42", "--run-task=backup", "--task-param=target=nas"]);
+    assert_eq!(crate::scheduled_task_id(&command.args).as_deref(), Some("42"));
+    let installed = installed_task("42", ScheduleTime::Cron("0 9 * * *".to_string()), &command.args, "crontab").unwrap();
+    assert_eq!(installed.task_name, "backup");
+    assert_eq!(installed.parameters, task.parameters);
+  }
+}
@@ -0,0 +1,330 @@
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+
+use super::{check_command, expand_cron_field, installed_task, SystemScheduler, TaskCommand, WEEKDAYS};
+use crate::models::{ScheduleTime, TaskInfo};
+use crate::recurrence::parse_cron;
+
+/// Comment line tagging the crontab entry that follows it.
+const TAG_PREFIX: &str = "# tauri-plugin-schedule-task";
+
+/// Installs tasks in the user's crontab.
+///
+/// Each task is one entry, preceded by a comment holding the app identifier and the task id, so
+/// that the entries of the app can be told apart from the rest of the crontab. Only cron
+/// schedules firing on the minute are supported.
+#[derive(Debug, Clone)]
+pub struct CrontabScheduler {
+  app_id: String,
+  command: PathBuf,
+}
+
+impl CrontabScheduler {
+  /// Uses the `crontab` command found in `PATH` for the app with the given identifier.
+  pub fn new(app_id: impl Into<String>) -> Self {
+    Self {
+      app_id: app_id.into(),
+      command: PathBuf::from("crontab"),
+    }
+  }
+
+  /// Uses another `crontab` command, which must understand `-l` and `-`.
+  pub fn with_command(mut self, command: impl Into<PathBuf>) -> Self {
+    self.command = command.into();
+    self
+  }
+
+  fn tag(&self, task_id: &str) -> String {
+    format!("{} {} {}", TAG_PREFIX, self.app_id, task_id)
+  }
+
+  fn read(&self) -> crate::Result<Vec<String>> {
+    let output = Command::new(&self.command).arg("-l").output()?;
+    if !output.status.success() {
+      let stderr = String::from_utf8_lossy(&output.stderr);
+      // `crontab -l` fails when the user has no crontab yet
+      if stderr.contains("no crontab") {
+        return Ok(Vec::new());
+      }
+      return Err(crate::Error::Generic(format!("crontab -l failed: {}", stderr.trim())));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).lines().map(str::to_string).collect())
+  }
+
+  fn write(&self, lines: &[String]) -> crate::Result<()> {
+    let mut child = Command::new(&self.command)
+      .arg("-")
+      .stdin(Stdio::piped())
+      .stderr(Stdio::piped())
+      .spawn()?;
+    let mut content = lines.join("\n");
+    content.push('\n');
+    child.stdin.take().expect("crontab stdin is piped").write_all(content.as_bytes())?;
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+      return Err(crate::Error::Generic(format!(
+        "crontab - failed: {}",
+        String::from_utf8_lossy(&output.stderr).trim()
+      )));
+    }
+    Ok(())
+  }
+
+  /// Drops the entry of a task from `lines`, returning whether it was there.
+  fn remove_entry(&self, lines: &mut Vec<String>, task_id: &str) -> bool {
+    let tag = self.tag(task_id);
+    let Some(index) = lines.iter().position(|line| line.trim() == tag) else {
+      return false;
+    };
+    let end = (index + 2).min(lines.len());
+    lines.drain(index..end);
+    true
+  }
+}
+
+impl SystemScheduler for CrontabScheduler {
+  fn name(&self) -> &'static str {
+    "crontab"
+  }
+
+  fn supports(&self, schedule: &ScheduleTime) -> bool {
+    matches!(schedule, ScheduleTime::Cron(expression) if crontab_fields(expression).is_some())
+  }
+
+  fn install(&self, task: &TaskInfo, command: &TaskCommand) -> crate::Result<()> {
+    let fields = match &task.schedule {
+      Some(ScheduleTime::Cron(expression)) => crontab_fields(expression),
+      _ => None,
+    }
+    .ok_or_else(|| crate::Error::Generic(format!("Task {} cannot be scheduled with crontab", task.task_id)))?;
+    check_command(task, command)?;
+
+    let mut lines = self.read()?;
+    self.remove_entry(&mut lines, &task.task_id);
+    lines.push(self.tag(&task.task_id));
+    lines.push(format!("{} {}", fields, command_line(command)));
+    self.write(&lines)
+  }
+
+  fn uninstall(&self, task_id: &str) -> crate::Result<()> {
+    let mut lines = self.read()?;
+    if self.remove_entry(&mut lines, task_id) {
+      self.write(&lines)?;
+    }
+    Ok(())
+  }
+
+  fn list(&self) -> crate::Result<Vec<TaskInfo>> {
+    let prefix = format!("{} {} ", TAG_PREFIX, self.app_id);
+    let lines = self.read()?;
+    let mut tasks = Vec::new();
+    for (index, line) in lines.iter().enumerate() {
+      let Some(task_id) = line.trim().strip_prefix(&prefix) else {
+        continue;
+      };
+      let Some((fields, args)) = lines.get(index + 1).and_then(|entry| parse_entry(entry)) else {
+        continue;
+      };
+      tasks.extend(installed_task(task_id, ScheduleTime::Cron(fields), &args, self.name()));
+    }
+    Ok(tasks)
+  }
+}
+
+/// The five crontab fields of a cron expression, when crontab can run it: it must fire on the
+/// minute and only use the syntax common to cron implementations.
+///
+/// cron fires when either the day of month or the weekday matches, where the app waits for both,
+/// so schedules restricting both are not supported.
+fn crontab_fields(expression: &str) -> Option<String> {
+  let (schedule, _) = parse_cron(expression).ok()?;
+  let mut fields = schedule.split_whitespace();
+  if fields.next()? != "0" {
+    return None;
+  }
+  let fields: Vec<&str> = fields.collect();
+  let portable = fields.len() == 5 && fields.iter().all(|field| {
+    field.split([',', '/', '-']).all(|part| {
+      part == "*"
+        || (!part.is_empty() && part.chars().all(|c| c.is_ascii_digit()))
+        || (part.len() == 3 && part.chars().all(|c| c.is_ascii_alphabetic()))
+    })
+  });
+  if !portable {
+    return None;
+  }
+  let days = expand_cron_field(fields[2], 1, 31, &[])?;
+  let mut weekdays: Vec<u32> = expand_cron_field(fields[4], 0, 7, &WEEKDAYS)?.into_iter().map(|day| day % 7).collect();
+  weekdays.sort_unstable();
+  weekdays.dedup();
+  if days.len() < 31 && weekdays.len() < 7 {
+    return None;
+  }
+  Some(fields.join(" "))
+}
+
+/// Renders the command of a crontab entry, quoting every argument for `sh`.
+fn command_line(command: &TaskCommand) -> String {
+  std::iter::once(command.program.to_string_lossy().into_owned())
+    .chain(command.args.iter().cloned())
+    .map(|arg| format!("'{}'", arg.replace('\'', "'\\''")))
+    .collect::<Vec<_>>()
+    .join(" ")
+    // cron turns unescaped `%` into newlines
+    .replace('%', "\\%")
+}
+
+/// Splits a crontab entry written by [`command_line`] into its schedule and its arguments, the
+/// program left out.
+fn parse_entry(entry: &str) -> Option<(String, Vec<String>)> {
+  let entry = entry.trim();
+  let mut rest = entry;
+  for _ in 0..5 {
+    let end = rest.find(char::is_whitespace)?;
+    rest = rest[end..].trim_start();
+  }
+  let fields = entry[..entry.len() - rest.len()].split_whitespace().collect::<Vec<_>>().join(" ");
+  let mut words = split_words(&rest.replace("\\%", "%"))?;
+  if words.is_empty() {
+    return None;
+  }
+  words.remove(0);
+  Some((fields, words))
+}
+
+/// Splits a command line quoted by [`command_line`] into words.
+fn split_words(line: &str) -> Option<Vec<String>> {
+  let mut words = Vec::new();
+  let mut word: Option<String> = None;
+  let mut chars = line.chars();
+  while let Some(c) = chars.next() {
+    match c {
+      '\'' => {
+        let word = word.get_or_insert_with(String::new);
+        loop {
+          match chars.next()? {
+            '\'' => break,
+            c => word.push(c),
+          }
+        }
+      }
+      '\\' => word.get_or_insert_with(String::new).push(chars.next()?),
+      c if c.is_whitespace() => words.extend(word.take()),
+      c => word.get_or_insert_with(String::new).push(c),
+    }
+  }
+  words.extend(word);
+  Some(words)
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+  use super::*;
+  use std::os::unix::fs::PermissionsExt;
+
+  /// A `crontab` script keeping the crontab in a file next to it.
+  fn fake_crontab(name: &str) -> (CrontabScheduler, PathBuf) {
+    let dir = std::env::temp_dir().join(format!("schedule-task-crontab-{}-{}", std::process::id(), name));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    let script = dir.join("crontab");
+    std::fs::write(&script, concat!(
+      "#!/bin/sh\n",
+      "file=\"$(dirname \"$0\")/crontab.txt\"\n",
+      "case \"$1\" in\n",
+      "  -l) [ -f \"$file\" ] || { echo \"no crontab for user\" >&2; exit 1; }; cat \"$file\" ;;\n",
+      "  -) cat > \"$file\" ;;\n",
+      "esac\n",
+    )).unwrap();
+    std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+    (CrontabScheduler::new("com.example.app").with_command(&script), dir.join("crontab.txt"))
+  }
+
+  fn task(task_id: &str, expression: &str, args: &[&str]) -> TaskInfo {
+    installed_task(task_id, ScheduleTime::Cron(expression.to_string()), args, "crontab").unwrap()
+  }
+
+  #[test]
+  fn installs_lists_and_uninstalls_entries() {
+    let (crontab, file) = fake_crontab("round-trip");
+    std::fs::write(&file, "MAILTO=me@example.com\n0 1 * * * /usr/bin/backup\n").unwrap();
+    let backup = task("1", "0 9 * * MON-FRI", &["--run-task=backup", "--task-param=target=nas"]);
+    let cleanup = task("2", "*/30 * * * *", &["--run-task=cleanup"]);
+    for task in [&backup, &cleanup, &backup] {
+      crontab.install(task, &TaskCommand::new("/usr/bin/app", task)).unwrap();
+    }
+
+    let content = std::fs::read_to_string(&file).unwrap();
+    assert_eq!(content, concat!(
+      "MAILTO=me@example.com\n",
+      "0 1 * * * /usr/bin/backup\n",
+      "# tauri-plugin-schedule-task com.example.app 2\n",
+      "*/30 * * * * '/usr/bin/app' '--task-id=2' '--run-task=cleanup'\n",
+      "# tauri-plugin-schedule-task com.example.app 1\n",
+      "0 9 * * MON-FRI '/usr/bin/app' '--task-id=1' '--run-task=backup' '--task-param=target=nas'\n",
+    ));
+    let mut listed = crontab.list().unwrap();
+    listed.sort_by(|a, b| a.task_id.cmp(&b.task_id));
+    assert_eq!(listed.iter().map(|task| task.task_name.as_str()).collect::<Vec<_>>(), ["backup", "cleanup"]);
+    assert_eq!(listed[0].parameters, backup.parameters);
+    assert!(matches!(&listed[1].schedule, Some(ScheduleTime::Cron(fields)) if fields == "*/30 * * * *"));
+
+    crontab.uninstall("1").unwrap();
+    crontab.uninstall("3").unwrap();
+    assert_eq!(crontab.list().unwrap().len(), 1);
+    crontab.uninstall("2").unwrap();
+    assert!(crontab.list().unwrap().is_empty());
+    assert_eq!(std::fs::read_to_string(&file).unwrap(), "MAILTO=me@example.com\n0 1 * * * /usr/bin/backup\n");
+  }
+
+  #[test]
+  fn escapes_percent_signs_and_quotes() {
+    let (crontab, file) = fake_crontab("escaping");
+    // a program printing its arguments, one per line
+    let program = file.with_file_name("args");
+    std::fs::write(&program, "#!/bin/sh\nfor arg in \"$@\"; do printf '%s\\n' \"$arg\"; done\n").unwrap();
+    std::fs::set_permissions(&program, std::fs::Permissions::from_mode(0o755)).unwrap();
+    let task = task("1", "0 9 * * *", &["--run-task=report", "--task-param=format=%Y-%m-%d", "--task-param=title=it's \"done\" $HOME `id`"]);
+    let command = TaskCommand::new(&program, &task);
+    crontab.install(&task, &command).unwrap();
+
+    let content = std::fs::read_to_string(&file).unwrap();
+    let entry = content.lines().nth(1).unwrap();
+    assert!(entry.contains("'--task-param=format=\\%Y-\\%m-\\%d'"));
+    assert!(entry.contains("'--task-param=title=it'\\''s \"done\" $HOME `id`'"));
+    // cron unescapes `\%` and hands the rest of the entry to `sh`
+    let command_line = entry.splitn(6, ' ').nth(5).unwrap().replace("\\%", "%");
+    let output = Command::new("sh").arg("-c").arg(command_line).output().unwrap();
+    let args: Vec<String> = String::from_utf8(output.stdout).unwrap().lines().map(str::to_string).collect();
+    assert_eq!(args, command.args);
+    assert_eq!(crontab.list().unwrap()[0].parameters, task.parameters);
+  }
+
+  #[test]
+  fn rejects_restricted_days_of_month_and_weekdays() {
+    assert_eq!(crontab_fields("0 9 1 * MON").as_deref(), None);
+    assert_eq!(crontab_fields("0 9 1-15 * 1-5").as_deref(), None);
+    assert_eq!(crontab_fields("0 9 1 * *").as_deref(), Some("0 9 1 * *"));
+    assert_eq!(crontab_fields("0 9 * * MON").as_deref(), Some("0 9 * * MON"));
+    assert_eq!(crontab_fields("0 9 1 * 0-7").as_deref(), Some("0 9 1 * 0-7"));
+  }
+
+  #[test]
+  fn rejects_newlines_in_parameters() {
+    let (crontab, file) = fake_crontab("newline");
+    let task = task("1", "0 9 * * *", &["--run-task=backup", "--task-param=target=x\n* * * * * sh -c 'evil' #"]);
+    let command = TaskCommand::new("/usr/bin/app", &task);
+    assert!(crontab.install(&task, &command).is_err());
+    assert!(!file.exists());
+  }
+
+  #[test]
+  fn rejects_control_characters_in_task_names() {
+    let (crontab, file) = fake_crontab("name");
+    let task = task("1", "0 9 * * *", &["--run-task=backup\r"]);
+    let command = TaskCommand::new("/usr/bin/app", &task);
+    assert!(crontab.install(&task, &command).is_err());
+    assert!(!file.exists());
+  }
+}
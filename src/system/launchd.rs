@@ -0,0 +1,371 @@
+use chrono::Local;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use super::{check_command, expand_cron_field, installed_task, SystemScheduler, TaskCommand, MONTHS, WEEKDAYS};
+use crate::models::{IntervalSchedule, ScheduleTime, TaskInfo};
+use crate::recurrence::{parse_cron, parse_datetime};
+
+/// Key of the plist holding the schedule of the task, as JSON, so that it can be listed back.
+const SCHEDULE_KEY: &str = "ScheduleTaskSchedule";
+/// Most `StartCalendarInterval` entries a cron schedule is expanded into.
+const MAX_CALENDAR_INTERVALS: usize = 500;
+
+/// Installs tasks as launchd agents.
+///
+/// Each task is a plist named `<app identifier>.schedule-task.<task id>.plist` in the user's
+/// `LaunchAgents` directory, loaded with `launchctl`. Cron schedules firing on the minute (as
+/// `StartCalendarInterval`) and unbounded intervals (as `StartInterval`, counted from when the
+/// agent is loaded, so only those starting within one interval) are supported.
+///
+/// Writing the plists is kept apart from loading them, see [`LaunchdScheduler::write_plist`].
+#[derive(Debug, Clone)]
+pub struct LaunchdScheduler {
+  app_id: String,
+  agents_dir: PathBuf,
+  launchctl: PathBuf,
+}
+
+impl LaunchdScheduler {
+  /// Uses `~/Library/LaunchAgents` and the `launchctl` found in `PATH` for the app with the
+  /// given identifier.
+  pub fn new(app_id: impl Into<String>) -> Self {
+    let home = std::env::var_os("HOME").map(PathBuf::from).unwrap_or_default();
+    Self {
+      app_id: app_id.into(),
+      agents_dir: home.join("Library").join("LaunchAgents"),
+      launchctl: PathBuf::from("launchctl"),
+    }
+  }
+
+  /// Writes the plists to another directory.
+  pub fn with_agents_dir(mut self, agents_dir: impl Into<PathBuf>) -> Self {
+    self.agents_dir = agents_dir.into();
+    self
+  }
+
+  /// Uses another `launchctl` command.
+  pub fn with_launchctl(mut self, launchctl: impl Into<PathBuf>) -> Self {
+    self.launchctl = launchctl.into();
+    self
+  }
+
+  pub fn agents_dir(&self) -> &Path {
+    &self.agents_dir
+  }
+
+  /// The launchd label of a task.
+  pub fn label(&self, task_id: &str) -> String {
+    format!("{}.schedule-task.{}", self.app_id, task_id)
+  }
+
+  /// Where the plist of a task is written.
+  pub fn plist_path(&self, task_id: &str) -> PathBuf {
+    self.agents_dir.join(format!("{}.plist", self.label(task_id)))
+  }
+
+  /// Writes the plist of a task without loading it, returning its path.
+  pub fn write_plist(&self, task: &TaskInfo, command: &TaskCommand) -> crate::Result<PathBuf> {
+    check_command(task, command)?;
+    let plist = render_plist(task, command, &self.label(&task.task_id))?;
+    std::fs::create_dir_all(&self.agents_dir)?;
+    let path = self.plist_path(&task.task_id);
+    std::fs::write(&path, plist)?;
+    Ok(path)
+  }
+
+  fn launchctl(&self, args: &[&str]) -> crate::Result<()> {
+    let output = Command::new(&self.launchctl).args(args).output()?;
+    if !output.status.success() {
+      return Err(crate::Error::Generic(format!(
+        "launchctl {} failed: {}",
+        args.join(" "),
+        String::from_utf8_lossy(&output.stderr).trim()
+      )));
+    }
+    Ok(())
+  }
+}
+
+impl SystemScheduler for LaunchdScheduler {
+  fn name(&self) -> &'static str {
+    "launchd"
+  }
+
+  fn supports(&self, schedule: &ScheduleTime) -> bool {
+    start_keys(schedule).is_some()
+  }
+
+  fn install(&self, task: &TaskInfo, command: &TaskCommand) -> crate::Result<()> {
+    let path = self.plist_path(&task.task_id);
+    if path.exists() {
+      // launchd keeps the loaded definition until the agent is unloaded
+      let _ = self.launchctl(&["unload", &path.to_string_lossy()]);
+    }
+    let path = self.write_plist(task, command)?;
+    self.launchctl(&["load", "-w", &path.to_string_lossy()])
+  }
+
+  fn uninstall(&self, task_id: &str) -> crate::Result<()> {
+    let path = self.plist_path(task_id);
+    if !path.exists() {
+      return Ok(());
+    }
+    self.launchctl(&["unload", "-w", &path.to_string_lossy()])?;
+    std::fs::remove_file(path)?;
+    Ok(())
+  }
+
+  fn list(&self) -> crate::Result<Vec<TaskInfo>> {
+    let entries = match std::fs::read_dir(&self.agents_dir) {
+      Ok(entries) => entries,
+      Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+      Err(e) => return Err(e.into()),
+    };
+    let prefix = self.label("");
+    let mut tasks = Vec::new();
+    for entry in entries {
+      let entry = entry?;
+      let file_name = entry.file_name();
+      let Some(task_id) = file_name.to_str()
+        .and_then(|name| name.strip_prefix(&prefix))
+        .and_then(|name| name.strip_suffix(".plist"))
+      else {
+        continue;
+      };
+      let plist = std::fs::read_to_string(entry.path())?;
+      let schedule = plist_string(&plist, SCHEDULE_KEY).and_then(|schedule| serde_json::from_str(&schedule).ok());
+      let args = plist_strings(&plist, "ProgramArguments");
+      if let (Some(schedule), Some(args)) = (schedule, args) {
+        tasks.extend(installed_task(task_id, schedule, args.get(1..).unwrap_or_default(), self.name()));
+      }
+    }
+    Ok(tasks)
+  }
+}
+
+/// Renders the launchd plist running a task.
+fn render_plist(task: &TaskInfo, command: &TaskCommand, label: &str) -> crate::Result<String> {
+  let schedule = task.schedule.as_ref()
+    .ok_or_else(|| crate::Error::Generic(format!("Task {} has no schedule", task.task_id)))?;
+  let start_keys = start_keys(schedule)
+    .ok_or_else(|| crate::Error::Generic(format!("Task {} cannot be scheduled with launchd", task.task_id)))?;
+
+  let mut plist = String::from(concat!(
+    "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+    "<!DOCTYPE plist PUBLIC \"-//Apple//DTD PLIST 1.0//EN\" \"http://www.apple.com/DTDs/PropertyList-1.0.dtd\">\n",
+    "<plist version=\"1.0\">\n",
+    "<dict>\n",
+  ));
+  plist.push_str(&format!("  <key>Label</key>\n  <string>{}</string>\n", escape(label)));
+  plist.push_str("  <key>ProgramArguments</key>\n  <array>\n");
+  for arg in std::iter::once(command.program.to_string_lossy().into_owned()).chain(command.args.iter().cloned()) {
+    plist.push_str(&format!("    <string>{}</string>\n", escape(&arg)));
+  }
+  plist.push_str("  </array>\n");
+  plist.push_str(&start_keys);
+  plist.push_str(&format!(
+    "  <key>{}</key>\n  <string>{}</string>\n",
+    SCHEDULE_KEY,
+    escape(&serde_json::to_string(schedule)?)
+  ));
+  plist.push_str("</dict>\n</plist>\n");
+  Ok(plist)
+}
+
+/// The `StartCalendarInterval` or `StartInterval` keys firing a task on `schedule`, when
+/// launchd can express it.
+fn start_keys(schedule: &ScheduleTime) -> Option<String> {
+  match schedule {
+    ScheduleTime::Cron(expression) => {
+      let mut keys = String::from("  <key>StartCalendarInterval</key>\n  <array>\n");
+      for interval in calendar_intervals(expression)? {
+        keys.push_str("    <dict>\n");
+        for (key, value) in interval {
+          keys.push_str(&format!("      <key>{}</key>\n      <integer>{}</integer>\n", key, value));
+        }
+        keys.push_str("    </dict>\n");
+      }
+      keys.push_str("  </array>\n");
+      Some(keys)
+    }
+    ScheduleTime::Interval(IntervalSchedule { every, start_at, end_at: None, max_runs: None }) if *every > 0 => {
+      // the first run comes `every` seconds after the agent is loaded, which must not be before `start_at`
+      let starts_later = |start_at: &String| {
+        parse_datetime(start_at).map_or(true, |start_at| {
+          start_at > Local::now() + chrono::Duration::seconds((*every).min(i64::MAX as u64) as i64)
+        })
+      };
+      if start_at.as_ref().is_some_and(starts_later) {
+        return None;
+      }
+      Some(format!("  <key>StartInterval</key>\n  <integer>{}</integer>\n", every))
+    }
+    _ => None,
+  }
+}
+
+/// Expands a cron expression into `StartCalendarInterval` entries, a key left out matching
+/// every value.
+///
+/// launchd fires when either the day of month or the weekday matches, so schedules
+/// restricting both are not supported, nor are those expanding into too many entries.
+fn calendar_intervals(expression: &str) -> Option<Vec<Vec<(&'static str, u32)>>> {
+  let (schedule, _) = parse_cron(expression).ok()?;
+  let fields: Vec<&str> = schedule.split_whitespace().collect();
+  let [second, minute, hour, day, month, weekday] = fields[..] else {
+    return None;
+  };
+  if expand_cron_field(second, 0, 59, &[])? != [0] {
+    return None;
+  }
+  let restricted = |field: &str, min: u32, max: u32, names: &[&str]| -> Option<Option<Vec<u32>>> {
+    let values = expand_cron_field(field, min, max, names)?;
+    Some(if values.len() as u32 == max - min + 1 { None } else { Some(values) })
+  };
+  // cron weekdays are 0 to 7, both meaning Sunday
+  let weekdays = {
+    let mut weekdays: Vec<u32> = expand_cron_field(weekday, 0, 7, &WEEKDAYS)?.into_iter().map(|day| day % 7).collect();
+    weekdays.sort_unstable();
+    weekdays.dedup();
+    (weekdays.len() < 7).then_some(weekdays)
+  };
+  let fields = [
+    ("Minute", restricted(minute, 0, 59, &[])?),
+    ("Hour", restricted(hour, 0, 23, &[])?),
+    ("Day", restricted(day, 1, 31, &[])?),
+    ("Month", restricted(month, 1, 12, &MONTHS)?),
+    ("Weekday", weekdays),
+  ];
+  if fields[2].1.is_some() && fields[4].1.is_some() {
+    return None;
+  }
+
+  let mut intervals: Vec<Vec<(&'static str, u32)>> = vec![Vec::new()];
+  for (key, values) in fields {
+    let Some(values) = values else {
+      continue;
+    };
+    if intervals.len() * values.len() > MAX_CALENDAR_INTERVALS {
+      return None;
+    }
+    intervals = intervals.into_iter()
+      .flat_map(|interval| values.iter().map(move |value| {
+        let mut interval = interval.clone();
+        interval.push((key, *value));
+        interval
+      }))
+      .collect();
+  }
+  Some(intervals)
+}
+
+fn escape(value: &str) -> String {
+  value.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+fn unescape(value: &str) -> String {
+  value.replace("&lt;", "<").replace("&gt;", ">").replace("&amp;", "&")
+}
+
+/// The `<string>` value of `key` in a plist written by [`render_plist`].
+fn plist_string(plist: &str, key: &str) -> Option<String> {
+  let rest = &plist[plist.find(&format!("<key>{}</key>", key))?..];
+  let start = rest.find("<string>")? + "<string>".len();
+  let end = rest[start..].find("</string>")?;
+  Some(unescape(&rest[start..start + end]))
+}
+
+/// The `<string>` values of the `<array>` of `key` in a plist written by [`render_plist`].
+fn plist_strings(plist: &str, key: &str) -> Option<Vec<String>> {
+  let rest = &plist[plist.find(&format!("<key>{}</key>", key))?..];
+  let array = &rest[rest.find("<array>")?..rest.find("</array>")?];
+  Some(
+    array.split("<string>")
+      .skip(1)
+      .filter_map(|value| value.split_once("</string>"))
+      .map(|(value, _)| unescape(value))
+      .collect(),
+  )
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn agents(name: &str) -> LaunchdScheduler {
+    let dir = std::env::temp_dir().join(format!("schedule-task-launchd-{}-{}", std::process::id(), name));
+    let _ = std::fs::remove_dir_all(&dir);
+    LaunchdScheduler::new("com.example.app").with_agents_dir(dir)
+  }
+
+  fn task(schedule: ScheduleTime) -> TaskInfo {
+    installed_task("1", schedule, &["--run-task=backup", "--task-param=target=<nas> & co"], "launchd").unwrap()
+  }
+
+  fn write(launchd: &LaunchdScheduler, task: &TaskInfo) -> crate::Result<String> {
+    let path = launchd.write_plist(task, &TaskCommand::new("/Applications/App.app/Contents/MacOS/app", task))?;
+    assert_eq!(path, launchd.plist_path("1"));
+    Ok(std::fs::read_to_string(path).unwrap())
+  }
+
+  fn interval(every: u64, start_at: Option<chrono::DateTime<Local>>) -> ScheduleTime {
+    ScheduleTime::Interval(IntervalSchedule {
+      every,
+      start_at: start_at.map(|start_at| start_at.to_rfc3339()),
+      end_at: None,
+      max_runs: None,
+    })
+  }
+
+  #[test]
+  fn rejects_intervals_starting_after_their_first_period() {
+    let now = Local::now();
+    assert!(start_keys(&interval(3600, None)).is_some());
+    assert!(start_keys(&interval(3600, Some(now - chrono::Duration::hours(5)))).is_some());
+    assert!(start_keys(&interval(3600, Some(now + chrono::Duration::minutes(30)))).is_some());
+    assert!(start_keys(&interval(3600, Some(now + chrono::Duration::hours(2)))).is_none());
+  }
+
+  #[test]
+  fn expands_cron_schedules_into_calendar_intervals() {
+    let launchd = agents("calendar");
+    let plist = write(&launchd, &task(ScheduleTime::Cron("30 9,17 * * MON-FRI".to_string()))).unwrap();
+    let intervals = plist.split("<key>StartCalendarInterval</key>").nth(1).unwrap();
+    let intervals = &intervals[..intervals.find("</array>").unwrap()];
+    assert_eq!(intervals.matches("<dict>").count(), 10);
+    assert!(intervals.starts_with(concat!(
+      "\n  <array>\n",
+      "    <dict>\n",
+      "      <key>Minute</key>\n      <integer>30</integer>\n",
+      "      <key>Hour</key>\n      <integer>9</integer>\n",
+      "      <key>Weekday</key>\n      <integer>1</integer>\n",
+      "    </dict>\n",
+    )));
+    assert!(!intervals.contains("<key>Day</key>") && !intervals.contains("<key>Month</key>"));
+    assert!(plist.contains("    <string>--task-param=target=&lt;nas&gt; &amp; co</string>\n"));
+
+    let listed = launchd.list().unwrap();
+    assert_eq!(listed.len(), 1);
+    assert_eq!(listed[0].task_name, "backup");
+    assert!(matches!(&listed[0].schedule, Some(ScheduleTime::Cron(expression)) if expression == "30 9,17 * * MON-FRI"));
+    assert_eq!(listed[0].parameters.as_ref().and_then(|parameters| parameters.get_str("target")), Some("<nas> & co"));
+  }
+
+  #[test]
+  fn writes_intervals_as_start_intervals() {
+    let plist = write(&agents("interval"), &task(interval(900, None))).unwrap();
+    assert!(plist.contains("  <key>StartInterval</key>\n  <integer>900</integer>\n"));
+    assert!(!plist.contains("StartCalendarInterval"));
+  }
+
+  #[test]
+  fn rejects_restricted_days_of_month_and_weekdays() {
+    let launchd = agents("dom-dow");
+    assert!(write(&launchd, &task(ScheduleTime::Cron("0 9 1 * MON".to_string()))).is_err());
+    assert!(!launchd.plist_path("1").exists());
+    assert!(!launchd.supports(&ScheduleTime::Cron("0 9 1-7 * 1".to_string())));
+    assert!(launchd.supports(&ScheduleTime::Cron("0 9 1 * *".to_string())));
+    assert!(launchd.supports(&ScheduleTime::Cron("0 9 * * 0,7".to_string())));
+  }
+}
@@ -0,0 +1,471 @@
+use chrono::Utc;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use super::{check_command, expand_cron_field, installed_task, SystemScheduler, TaskCommand, MONTHS, WEEKDAYS};
+use crate::models::{IntervalSchedule, ScheduleTime, TaskInfo};
+use crate::recurrence::{parse_cron, parse_datetime};
+
+/// Comment of the timer unit holding the schedule of the task, as JSON.
+const SCHEDULE_COMMENT: &str = "# Schedule: ";
+
+/// Installs tasks as `systemd --user` timers.
+///
+/// Each task gets a `.service` unit launching the app and a `.timer` unit triggering it, both
+/// named `schedule-task-<app identifier>-<task id>`. Timers are `Persistent`, so runs of cron
+/// schedules and one-time datetimes missed while the machine was off happen at the next boot.
+/// That only holds for `OnCalendar=` though: the repeats of intervals are `OnUnitActiveSec=`,
+/// counted again from each boot, and catch up nothing. Cron schedules, one-time datetimes and
+/// unbounded intervals are supported.
+#[derive(Debug, Clone)]
+pub struct SystemdScheduler {
+  app_id: String,
+  unit_dir: PathBuf,
+  systemctl: PathBuf,
+  wake_system: bool,
+}
+
+impl SystemdScheduler {
+  /// Uses the user unit directory (`$XDG_CONFIG_HOME/systemd/user`) and the `systemctl` found
+  /// in `PATH` for the app with the given identifier.
+  pub fn new(app_id: impl Into<String>) -> Self {
+    Self {
+      app_id: app_id.into(),
+      unit_dir: default_unit_dir(),
+      systemctl: PathBuf::from("systemctl"),
+      wake_system: false,
+    }
+  }
+
+  /// Writes the units to another directory.
+  pub fn with_unit_dir(mut self, unit_dir: impl Into<PathBuf>) -> Self {
+    self.unit_dir = unit_dir.into();
+    self
+  }
+
+  /// Uses another `systemctl` command, which is always passed `--user`.
+  pub fn with_systemctl(mut self, systemctl: impl Into<PathBuf>) -> Self {
+    self.systemctl = systemctl.into();
+    self
+  }
+
+  /// Lets the timers wake the machine from suspend (`WakeSystem=`).
+  pub fn with_wake_system(mut self, wake_system: bool) -> Self {
+    self.wake_system = wake_system;
+    self
+  }
+
+  pub fn unit_dir(&self) -> &Path {
+    &self.unit_dir
+  }
+
+  fn unit_prefix(&self) -> String {
+    let app_id: String = self.app_id.chars()
+      .map(|c| if c.is_ascii_alphanumeric() || "-_.".contains(c) { c } else { '_' })
+      .collect();
+    format!("schedule-task-{}-", app_id)
+  }
+
+  fn unit_name(&self, task_id: &str) -> String {
+    format!("{}{}", self.unit_prefix(), task_id)
+  }
+
+  fn systemctl(&self, args: &[&str]) -> crate::Result<()> {
+    let output = Command::new(&self.systemctl).arg("--user").args(args).output()?;
+    if !output.status.success() {
+      return Err(crate::Error::Generic(format!(
+        "systemctl --user {} failed: {}",
+        args.join(" "),
+        String::from_utf8_lossy(&output.stderr).trim()
+      )));
+    }
+    Ok(())
+  }
+}
+
+impl SystemScheduler for SystemdScheduler {
+  fn name(&self) -> &'static str {
+    "systemd"
+  }
+
+  fn supports(&self, schedule: &ScheduleTime) -> bool {
+    timer_triggers(schedule).is_some()
+  }
+
+  fn install(&self, task: &TaskInfo, command: &TaskCommand) -> crate::Result<()> {
+    let unit_name = self.unit_name(&task.task_id);
+    let (service, timer) = render_units(task, command, &unit_name, self.wake_system)?;
+    std::fs::create_dir_all(&self.unit_dir)?;
+    std::fs::write(self.unit_dir.join(format!("{}.service", unit_name)), service)?;
+    std::fs::write(self.unit_dir.join(format!("{}.timer", unit_name)), timer)?;
+    self.systemctl(&["daemon-reload"])?;
+    self.systemctl(&["enable", "--now", &format!("{}.timer", unit_name)])
+  }
+
+  fn uninstall(&self, task_id: &str) -> crate::Result<()> {
+    let unit_name = self.unit_name(task_id);
+    let timer = self.unit_dir.join(format!("{}.timer", unit_name));
+    if !timer.exists() {
+      return Ok(());
+    }
+    self.systemctl(&["disable", "--now", &format!("{}.timer", unit_name)])?;
+    for unit in [timer, self.unit_dir.join(format!("{}.service", unit_name))] {
+      match std::fs::remove_file(unit) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+        _ => {}
+      }
+    }
+    self.systemctl(&["daemon-reload"])
+  }
+
+  fn list(&self) -> crate::Result<Vec<TaskInfo>> {
+    let entries = match std::fs::read_dir(&self.unit_dir) {
+      Ok(entries) => entries,
+      Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+      Err(e) => return Err(e.into()),
+    };
+    let prefix = self.unit_prefix();
+    let mut tasks = Vec::new();
+    for entry in entries {
+      let file_name = entry?.file_name();
+      let Some(task_id) = file_name.to_str()
+        .and_then(|name| name.strip_prefix(&prefix))
+        .and_then(|name| name.strip_suffix(".timer"))
+      else {
+        continue;
+      };
+      let unit_name = self.unit_name(task_id);
+      let timer = std::fs::read_to_string(self.unit_dir.join(format!("{}.timer", unit_name)))?;
+      let Ok(service) = std::fs::read_to_string(self.unit_dir.join(format!("{}.service", unit_name))) else {
+        continue;
+      };
+      let schedule = timer.lines()
+        .find_map(|line| line.strip_prefix(SCHEDULE_COMMENT))
+        .and_then(|schedule| serde_json::from_str(schedule).ok());
+      let args = service.lines()
+        .find_map(|line| line.strip_prefix("ExecStart="))
+        .and_then(parse_exec_start);
+      if let (Some(schedule), Some(args)) = (schedule, args) {
+        tasks.extend(installed_task(task_id, schedule, &args[1..], self.name()));
+      }
+    }
+    Ok(tasks)
+  }
+}
+
+/// Renders the `.service` and `.timer` units of a task.
+fn render_units(task: &TaskInfo, command: &TaskCommand, unit_name: &str, wake_system: bool) -> crate::Result<(String, String)> {
+  let schedule = task.schedule.as_ref()
+    .ok_or_else(|| crate::Error::Generic(format!("Task {} has no schedule", task.task_id)))?;
+  let triggers = timer_triggers(schedule)
+    .ok_or_else(|| crate::Error::Generic(format!("Task {} cannot be scheduled with systemd", task.task_id)))?;
+  check_command(task, command)?;
+
+  let service = format!(
+    "[Unit]\nDescription=Scheduled task {name}\n\n[Service]\nType=oneshot\nExecStart={exec}\n",
+    name = description(&task.task_name),
+    exec = exec_start(command),
+  );
+  let mut timer = format!(
+    "{comment}{schedule}\n[Unit]\nDescription=Timer of scheduled task {name}\n\n[Timer]\n",
+    comment = SCHEDULE_COMMENT,
+    schedule = serde_json::to_string(schedule)?,
+    name = description(&task.task_name),
+  );
+  for trigger in triggers {
+    timer.push_str(&trigger);
+    timer.push('\n');
+  }
+  timer.push_str("Persistent=true\nAccuracySec=1s\n");
+  if wake_system {
+    timer.push_str("WakeSystem=true\n");
+  }
+  timer.push_str(&format!("Unit={}.service\n\n[Install]\nWantedBy=timers.target\n", unit_name));
+  Ok((service, timer))
+}
+
+/// A task name as a `Description=` value, where `%` starts a specifier.
+fn description(task_name: &str) -> String {
+  task_name.replace('%', "%%")
+}
+
+/// The `[Timer]` settings firing a task on `schedule`, when systemd can express it.
+fn timer_triggers(schedule: &ScheduleTime) -> Option<Vec<String>> {
+  match schedule {
+    ScheduleTime::Cron(expression) => Some(vec![format!("OnCalendar={}", on_calendar(expression)?)]),
+    ScheduleTime::DateTime(datetime) => Some(vec![format!("OnCalendar={}", utc_calendar(datetime)?)]),
+    ScheduleTime::Interval(IntervalSchedule { every, start_at, end_at: None, max_runs: None }) if *every > 0 => {
+      let mut triggers = match start_at {
+        Some(start_at) => vec![format!("OnCalendar={}", utc_calendar(start_at)?)],
+        None => vec![format!("OnActiveSec={}s", every)],
+      };
+      triggers.push(format!("OnUnitActiveSec={}s", every));
+      Some(triggers)
+    }
+    _ => None,
+  }
+}
+
+/// A datetime as a `OnCalendar=` value, in UTC.
+fn utc_calendar(datetime: &str) -> Option<String> {
+  let datetime = parse_datetime(datetime).ok()?.with_timezone(&Utc);
+  Some(format!("{} UTC", datetime.format("%Y-%m-%d %H:%M:%S")))
+}
+
+/// Translates a cron expression into a `OnCalendar=` value.
+///
+/// Fields are expanded into lists, so steps and ranges of any kind are supported; the
+/// non-standard `L`, `W` and `#` of croner are not.
+fn on_calendar(expression: &str) -> Option<String> {
+  let (schedule, _) = parse_cron(expression).ok()?;
+  let fields: Vec<&str> = schedule.split_whitespace().collect();
+  let [second, minute, hour, day, month, weekday] = fields[..] else {
+    return None;
+  };
+  let second = calendar_field(second, 0, 59, &[])?;
+  let minute = calendar_field(minute, 0, 59, &[])?;
+  let hour = calendar_field(hour, 0, 23, &[])?;
+  let day = calendar_field(day, 1, 31, &[])?;
+  let month = calendar_field(month, 1, 12, &MONTHS)?;
+  // cron counts weekdays from Sunday, as 0 or 7, systemd lists them from Monday
+  let mut weekdays: Vec<usize> = expand_cron_field(weekday, 0, 7, &WEEKDAYS)?.into_iter()
+    .map(|day| (day as usize + 6) % 7)
+    .collect();
+  weekdays.sort_unstable();
+  weekdays.dedup();
+
+  let mut calendar = String::new();
+  if weekdays.len() < 7 {
+    let names: Vec<&str> = weekdays.iter().map(|day| SYSTEMD_WEEKDAYS[*day]).collect();
+    calendar.push_str(&names.join(","));
+    calendar.push(' ');
+  }
+  calendar.push_str(&format!("*-{}-{} {}:{}:{}", month, day, hour, minute, second));
+  Some(calendar)
+}
+
+/// systemd weekday names, from Monday.
+const SYSTEMD_WEEKDAYS: [&str; 7] = ["Mon", "Tue", "Wed", "Thu", "Fri", "Sat", "Sun"];
+
+/// A cron field as a `OnCalendar=` component: `*` when it matches every value, a list otherwise.
+fn calendar_field(field: &str, min: u32, max: u32, names: &[&str]) -> Option<String> {
+  let values = expand_cron_field(field, min, max, names)?;
+  if values.len() as u32 == max - min + 1 {
+    return Some("*".to_string());
+  }
+  Some(values.iter().map(|value| format!("{:02}", value)).collect::<Vec<_>>().join(","))
+}
+
+/// Renders the `ExecStart=` value of a command, quoting every argument.
+fn exec_start(command: &TaskCommand) -> String {
+  std::iter::once(command.program.to_string_lossy().into_owned())
+    .chain(command.args.iter().cloned())
+    .map(|arg| {
+      let arg = arg.replace('\\', "\\\\").replace('"', "\\\"");
+      // `%` starts a specifier and `$` a variable in unit files
+      format!("\"{}\"", arg.replace('%', "%%").replace('$', "$$"))
+    })
+    .collect::<Vec<_>>()
+    .join(" ")
+}
+
+/// Splits an `ExecStart=` value written by [`exec_start`] back into its words.
+fn parse_exec_start(value: &str) -> Option<Vec<String>> {
+  let value = value.replace("%%", "%").replace("$$", "$");
+  let mut words = Vec::new();
+  let mut chars = value.chars();
+  while let Some(c) = chars.next() {
+    match c {
+      '"' => {
+        let mut word = String::new();
+        loop {
+          match chars.next()? {
+            '"' => break,
+            '\\' => word.push(chars.next()?),
+            c => word.push(c),
+          }
+        }
+        words.push(word);
+      }
+      c if c.is_whitespace() => {}
+      _ => return None,
+    }
+  }
+  Some(words)
+}
+
+fn default_unit_dir() -> PathBuf {
+  let config_dir = std::env::var_os("XDG_CONFIG_HOME")
+    .filter(|dir| !dir.is_empty())
+    .map(PathBuf::from)
+    .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+    .unwrap_or_else(|| PathBuf::from(".config"));
+  config_dir.join("systemd").join("user")
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn task(args: &[&str]) -> TaskInfo {
+    scheduled_task(ScheduleTime::Cron("0 9 * * *".to_string()), args)
+  }
+
+  fn scheduled_task(schedule: ScheduleTime, args: &[&str]) -> TaskInfo {
+    installed_task("1", schedule, args, "systemd").unwrap()
+  }
+
+  /// The `[Timer]` settings of the timer unit rendered for `schedule`.
+  fn triggers(schedule: ScheduleTime) -> Vec<String> {
+    let task = scheduled_task(schedule, &["--run-task=backup"]);
+    let (_, timer) = render_units(&task, &TaskCommand::new("/usr/bin/app", &task), "unit", false).unwrap();
+    timer.lines()
+      .filter(|line| line.starts_with("OnCalendar=") || line.starts_with("OnActiveSec=") || line.starts_with("OnUnitActiveSec="))
+      .map(str::to_string)
+      .collect()
+  }
+
+  fn interval(every: u64, start_at: Option<&str>, max_runs: Option<u32>) -> ScheduleTime {
+    ScheduleTime::Interval(IntervalSchedule {
+      every,
+      start_at: start_at.map(str::to_string),
+      end_at: None,
+      max_runs,
+    })
+  }
+
+  /// A `systemctl` script logging its arguments to a file next to it, with a unit directory of its own.
+  #[cfg(unix)]
+  fn fake_systemctl(name: &str) -> (SystemdScheduler, PathBuf) {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = std::env::temp_dir().join(format!("schedule-task-systemd-{}-{}", std::process::id(), name));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    let script = dir.join("systemctl");
+    std::fs::write(&script, "#!/bin/sh
+echo \"$@\" >> \"$(dirname \"$0\")/systemctl.log\"\n").unwrap();
+    std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+    let systemd = SystemdScheduler::new("com.example.app").with_unit_dir(dir.join("units")).with_systemctl(&script);
+    (systemd, dir.join("systemctl.log"))
+  }
+
+  #[test]
+  fn renders_cron_schedules_as_on_calendar() {
+    let calendar = |expression: &str| on_calendar(expression).unwrap();
+    assert_eq!(calendar("0 9 * * *"), "*-*-* 09:00:00");
+    assert_eq!(calendar("*/15 9-17 * * MON-FRI"), "Mon,Tue,Wed,Thu,Fri *-*-* 09,10,11,12,13,14,15,16,17:00,15,30,45:00");
+    // Sunday is 0 or 7 to cron, the last weekday to systemd
+    assert_eq!(calendar("30 8 * * 0,7"), "Sun *-*-* 08:30:00");
+    assert_eq!(calendar("0 12 * * 1,SUN"), "Mon,Sun *-*-* 12:00:00");
+    assert_eq!(calendar("0 0 1 */3 *"), "*-01,04,07,10-01 00:00:00");
+    assert_eq!(calendar("0 6 1,15 JAN,JUL *"), "*-01,07-01,15 06:00:00");
+    assert_eq!(calendar("0 0 * * 0-7"), "*-*-* 00:00:00");
+    assert!(on_calendar("0 0 L * *").is_none());
+  }
+
+  #[test]
+  fn renders_cron_timers() {
+    let task = scheduled_task(ScheduleTime::Cron("30 9 * * MON".to_string()), &["--run-task=backup", "--task-param=target=nas"]);
+    let command = TaskCommand::new("/usr/bin/app", &task);
+    let (service, timer) = render_units(&task, &command, "schedule-task-com.example.app-1", true).unwrap();
+    assert_eq!(service, concat!(
+      "[Unit]\nDescription=Scheduled task backup\n\n",
+      "[Service]\nType=oneshot\n",
+      "ExecStart=\"/usr/bin/app\" \"--task-id=1\" \"--run-task=backup\" \"--task-param=target=nas\"\n",
+    ));
+    assert_eq!(timer, concat!(
+      "# Schedule: {\"cron\":\"30 9 * * MON\"}\n",
+      "[Unit]\nDescription=Timer of scheduled task backup\n\n",
+      "[Timer]\n",
+      "OnCalendar=Mon *-*-* 09:30:00\n",
+      "Persistent=true\nAccuracySec=1s\nWakeSystem=true\n",
+      "Unit=schedule-task-com.example.app-1.service\n\n",
+      "[Install]\nWantedBy=timers.target\n",
+    ));
+  }
+
+  #[test]
+  fn escapes_specifiers_in_descriptions() {
+    let task = task(&["--run-task=50% sync"]);
+    let (service, timer) = render_units(&task, &TaskCommand::new("/usr/bin/app", &task), "unit", false).unwrap();
+    assert!(service.contains("\nDescription=Scheduled task 50%% sync\n"));
+    assert!(service.contains("\"--run-task=50%% sync\""));
+    assert!(timer.contains("\nDescription=Timer of scheduled task 50%% sync\n"));
+  }
+
+  #[test]
+  fn renders_one_time_timers_in_utc() {
+    assert_eq!(triggers(ScheduleTime::DateTime("2026-03-01T10:30:00+01:00".to_string())), ["OnCalendar=2026-03-01 09:30:00 UTC"]);
+  }
+
+  #[test]
+  fn renders_interval_timers() {
+    assert_eq!(triggers(interval(900, None, None)), ["OnActiveSec=900s", "OnUnitActiveSec=900s"]);
+    assert_eq!(
+      triggers(interval(3600, Some("2026-03-01T08:00:00-03:00"), None)),
+      ["OnCalendar=2026-03-01 11:00:00 UTC", "OnUnitActiveSec=3600s"],
+    );
+    // bounded intervals are left to the app
+    assert!(timer_triggers(&interval(900, None, Some(3))).is_none());
+    assert!(timer_triggers(&interval(0, None, None)).is_none());
+  }
+
+  #[cfg(unix)]
+  #[test]
+  fn installs_lists_and_uninstalls_units() {
+    let (systemd, log) = fake_systemctl("round-trip");
+    let task = scheduled_task(ScheduleTime::Cron("0 9 * * *".to_string()), &["--run-task=backup", "--task-param=target=nas"]);
+    systemd.install(&task, &TaskCommand::new("/usr/bin/app", &task)).unwrap();
+
+    let units = systemd.unit_dir().to_path_buf();
+    assert!(units.join("schedule-task-com.example.app-1.service").exists());
+    assert!(units.join("schedule-task-com.example.app-1.timer").exists());
+    assert_eq!(std::fs::read_to_string(&log).unwrap(), "--user daemon-reload\n--user enable --now schedule-task-com.example.app-1.timer\n");
+    let listed = systemd.list().unwrap();
+    assert_eq!(listed.len(), 1);
+    assert_eq!(listed[0].task_name, "backup");
+    assert_eq!(listed[0].parameters, task.parameters);
+    assert!(matches!(&listed[0].schedule, Some(ScheduleTime::Cron(expression)) if expression == "0 9 * * *"));
+
+    std::fs::remove_file(&log).unwrap();
+    systemd.uninstall("2").unwrap();
+    assert!(!log.exists());
+    systemd.uninstall("1").unwrap();
+    assert_eq!(std::fs::read_to_string(&log).unwrap(), "--user disable --now schedule-task-com.example.app-1.timer\n--user daemon-reload\n");
+    assert!(std::fs::read_dir(&units).unwrap().next().is_none());
+    assert!(systemd.list().unwrap().is_empty());
+  }
+
+  #[cfg(unix)]
+  #[test]
+  fn reports_systemctl_failures() {
+    let (systemd, _) = fake_systemctl("failure");
+    let systemd = systemd.with_systemctl("false");
+    let task = task(&["--run-task=backup"]);
+    assert!(systemd.install(&task, &TaskCommand::new("/usr/bin/app", &task)).is_err());
+  }
+
+  #[test]
+  fn rejects_newlines_in_task_names() {
+    let task = task(&["--run-task=backup\nExecStartPre=/bin/evil"]);
+    let command = TaskCommand::new("/usr/bin/app", &task);
+    assert!(render_units(&task, &command, "unit", false).is_err());
+  }
+
+  #[test]
+  fn rejects_newlines_in_parameters() {
+    let task = task(&["--run-task=backup", "--task-param=target=x\nExecStartPre=/bin/evil"]);
+    let command = TaskCommand::new("/usr/bin/app", &task);
+    assert!(render_units(&task, &command, "unit", false).is_err());
+  }
+
+  #[test]
+  fn renders_quoted_exec_start() {
+    let task = task(&["--run-task=backup", "--task-param=target=50% \"off\" $HOME"]);
+    let command = TaskCommand::new("/usr/bin/app", &task);
+    let (service, _) = render_units(&task, &command, "unit", false).unwrap();
+    let exec = service.lines().find_map(|line| line.strip_prefix("ExecStart=")).unwrap();
+    assert_eq!(parse_exec_start(exec).unwrap()[1..], command.args[..]);
+  }
+}
@@ -0,0 +1,460 @@
+use chrono::Utc;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::Command;
+use uuid::Uuid;
+
+use super::{check_command, expand_cron_field, installed_task, SystemScheduler, TaskCommand, MONTHS, WEEKDAYS};
+use crate::models::{IntervalSchedule, ScheduleTime, TaskInfo};
+use crate::recurrence::{parse_cron, parse_datetime};
+
+/// Task Scheduler folder the tasks of every app are registered under.
+const TASK_FOLDER: &str = "ScheduleTask";
+/// Most triggers Task Scheduler accepts for one task.
+const MAX_TRIGGERS: usize = 48;
+/// Start date of the calendar triggers, in the past so that they are active right away.
+const CALENDAR_START_DATE: &str = "2000-01-01";
+
+/// Registers tasks in the Windows Task Scheduler with `schtasks`.
+///
+/// Each task is registered as `\ScheduleTask\<app identifier>\<task id>` from an XML
+/// definition rendered by [`render_task_xml`]. One-time datetimes, intervals of whole minutes
+/// and cron schedules that map to daily, weekly or monthly triggers are supported.
+#[derive(Debug, Clone)]
+pub struct WindowsTaskScheduler {
+  app_id: String,
+  schtasks: PathBuf,
+  wake_to_run: bool,
+}
+
+impl WindowsTaskScheduler {
+  /// Uses the `schtasks` found in `PATH` for the app with the given identifier.
+  pub fn new(app_id: impl Into<String>) -> Self {
+    Self {
+      app_id: app_id.into(),
+      schtasks: PathBuf::from("schtasks"),
+      wake_to_run: false,
+    }
+  }
+
+  /// Uses another `schtasks` command.
+  pub fn with_schtasks(mut self, schtasks: impl Into<PathBuf>) -> Self {
+    self.schtasks = schtasks.into();
+    self
+  }
+
+  /// Lets the tasks wake the machine from sleep (`WakeToRun`).
+  pub fn with_wake_to_run(mut self, wake_to_run: bool) -> Self {
+    self.wake_to_run = wake_to_run;
+    self
+  }
+
+  fn folder(&self) -> String {
+    format!("\\{}\\{}\\", TASK_FOLDER, self.app_id)
+  }
+
+  fn task_name(&self, task_id: &str) -> String {
+    format!("{}{}", self.folder(), task_id)
+  }
+
+  fn schtasks(&self, args: &[&str]) -> crate::Result<String> {
+    let output = Command::new(&self.schtasks).args(args).output()?;
+    if !output.status.success() {
+      return Err(crate::Error::Generic(format!(
+        "schtasks {} failed: {}",
+        args.join(" "),
+        String::from_utf8_lossy(&output.stderr).trim()
+      )));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+  }
+
+  fn is_registered(&self, task_name: &str) -> bool {
+    self.schtasks(&["/Query", "/TN", task_name]).is_ok()
+  }
+}
+
+impl SystemScheduler for WindowsTaskScheduler {
+  fn name(&self) -> &'static str {
+    "taskScheduler"
+  }
+
+  fn supports(&self, schedule: &ScheduleTime) -> bool {
+    triggers(schedule).is_some()
+  }
+
+  fn install(&self, task: &TaskInfo, command: &TaskCommand) -> crate::Result<()> {
+    let xml = render_task_xml(task, command, self.wake_to_run)?;
+    // a new file of its own, so that neither another app nor another user can hand schtasks a
+    // definition in its place
+    let path = std::env::temp_dir().join(format!("schedule-task-{}-{}-{}.xml", self.app_id, task.task_id, Uuid::new_v4()));
+    // schtasks reads the definition as UTF-16, as declared in it
+    let mut content = vec![0xFF, 0xFE];
+    content.extend(xml.encode_utf16().flat_map(u16::to_le_bytes));
+    OpenOptions::new().write(true).create_new(true).open(&path)?.write_all(&content)?;
+    let result = self.schtasks(&["/Create", "/XML", &path.to_string_lossy(), "/TN", &self.task_name(&task.task_id), "/F"]);
+    let _ = std::fs::remove_file(path);
+    result.map(|_| ())
+  }
+
+  fn uninstall(&self, task_id: &str) -> crate::Result<()> {
+    let task_name = self.task_name(task_id);
+    if !self.is_registered(&task_name) {
+      return Ok(());
+    }
+    self.schtasks(&["/Delete", "/TN", &task_name, "/F"]).map(|_| ())
+  }
+
+  fn list(&self) -> crate::Result<Vec<TaskInfo>> {
+    let folder = self.folder();
+    let output = self.schtasks(&["/Query", "/FO", "CSV", "/NH"])?;
+    let mut tasks = Vec::new();
+    for line in output.lines() {
+      let Some(task_name) = line.split(',').next().map(|name| name.trim_matches('"')) else {
+        continue;
+      };
+      let Some(task_id) = task_name.strip_prefix(&folder) else {
+        continue;
+      };
+      // a task with several triggers is listed once per trigger
+      if tasks.iter().any(|task: &TaskInfo| task.task_id == task_id) {
+        continue;
+      }
+      let xml = self.schtasks(&["/Query", "/XML", "/TN", task_name])?;
+      let schedule = xml_value(&xml, "Documentation").and_then(|schedule| serde_json::from_str(&schedule).ok());
+      let args = xml_value(&xml, "Arguments").map(|args| split_args(&args));
+      if let (Some(schedule), Some(args)) = (schedule, args) {
+        tasks.extend(installed_task(task_id, schedule, &args, self.name()));
+      }
+    }
+    Ok(tasks)
+  }
+}
+
+/// Renders the Task Scheduler XML definition running a task.
+///
+/// Only depends on its arguments, so that definitions can be compared against golden files.
+pub fn render_task_xml(task: &TaskInfo, command: &TaskCommand, wake_to_run: bool) -> crate::Result<String> {
+  let schedule = task.schedule.as_ref()
+    .ok_or_else(|| crate::Error::Generic(format!("Task {} has no schedule", task.task_id)))?;
+  let triggers = triggers(schedule)
+    .ok_or_else(|| crate::Error::Generic(format!("Task {} cannot be scheduled with the Task Scheduler", task.task_id)))?;
+  check_command(task, command)?;
+
+  let mut xml = String::from(concat!(
+    "<?xml version=\"1.0\" encoding=\"UTF-16\"?>\n",
+    "<Task version=\"1.2\" xmlns=\"http://schemas.microsoft.com/windows/2004/02/mit/task\">\n",
+    "  <RegistrationInfo>\n",
+  ));
+  xml.push_str(&format!("    <Description>Scheduled task {}</Description>\n", escape(&task.task_name)));
+  xml.push_str(&format!("    <Documentation>{}</Documentation>\n", escape(&serde_json::to_string(schedule)?)));
+  xml.push_str("  </RegistrationInfo>\n  <Triggers>\n");
+  for trigger in triggers {
+    xml.push_str(&trigger);
+  }
+  xml.push_str(concat!(
+    "  </Triggers>\n",
+    "  <Principals>\n",
+    "    <Principal id=\"Author\">\n",
+    "      <LogonType>InteractiveToken</LogonType>\n",
+    "      <RunLevel>LeastPrivilege</RunLevel>\n",
+    "    </Principal>\n",
+    "  </Principals>\n",
+    "  <Settings>\n",
+    "    <MultipleInstancesPolicy>IgnoreNew</MultipleInstancesPolicy>\n",
+    "    <DisallowStartIfOnBatteries>false</DisallowStartIfOnBatteries>\n",
+    "    <StopIfGoingOnBatteries>false</StopIfGoingOnBatteries>\n",
+    "    <StartWhenAvailable>true</StartWhenAvailable>\n",
+  ));
+  xml.push_str(&format!("    <WakeToRun>{}</WakeToRun>\n", wake_to_run));
+  xml.push_str(concat!(
+    "    <ExecutionTimeLimit>PT0S</ExecutionTimeLimit>\n",
+    "    <Enabled>true</Enabled>\n",
+    "  </Settings>\n",
+    "  <Actions Context=\"Author\">\n",
+    "    <Exec>\n",
+  ));
+  xml.push_str(&format!("      <Command>{}</Command>\n", escape(&command.program.to_string_lossy())));
+  let args: Vec<String> = command.args.iter().map(|arg| quote_arg(arg)).collect();
+  xml.push_str(&format!("      <Arguments>{}</Arguments>\n", escape(&args.join(" "))));
+  xml.push_str("    </Exec>\n  </Actions>\n</Task>\n");
+  Ok(xml)
+}
+
+/// The `<Triggers>` entries firing a task on `schedule`, when the Task Scheduler can express it.
+fn triggers(schedule: &ScheduleTime) -> Option<Vec<String>> {
+  match schedule {
+    ScheduleTime::DateTime(datetime) => Some(vec![time_trigger(&utc_boundary(datetime)?, None, None)]),
+    ScheduleTime::Interval(IntervalSchedule { every, start_at: Some(start_at), end_at, max_runs: None })
+      if *every >= 60 && every % 60 == 0 && *every <= 31 * 24 * 60 * 60 =>
+    {
+      let end_at = match end_at {
+        Some(end_at) => Some(utc_boundary(end_at)?),
+        None => None,
+      };
+      let repetition = format!("PT{}M", every / 60);
+      Some(vec![time_trigger(&utc_boundary(start_at)?, Some(&repetition), end_at.as_deref())])
+    }
+    ScheduleTime::Cron(expression) => calendar_triggers(expression),
+    _ => None,
+  }
+}
+
+fn time_trigger(start: &str, repetition: Option<&str>, end: Option<&str>) -> String {
+  let mut trigger = String::from("    <TimeTrigger>\n");
+  if let Some(repetition) = repetition {
+    trigger.push_str(&format!("      <Repetition>\n        <Interval>{}</Interval>\n      </Repetition>\n", repetition));
+  }
+  trigger.push_str(&format!("      <StartBoundary>{}</StartBoundary>\n", start));
+  if let Some(end) = end {
+    trigger.push_str(&format!("      <EndBoundary>{}</EndBoundary>\n", end));
+  }
+  trigger.push_str("      <Enabled>true</Enabled>\n    </TimeTrigger>\n");
+  trigger
+}
+
+/// A datetime as a trigger boundary, in UTC.
+fn utc_boundary(datetime: &str) -> Option<String> {
+  Some(parse_datetime(datetime).ok()?.with_timezone(&Utc).format("%Y-%m-%dT%H:%M:%SZ").to_string())
+}
+
+/// Translates a cron expression into calendar triggers, in local time.
+///
+/// The days are matched by a daily, weekly (weekdays) or monthly (days of month and months)
+/// schedule, restricting weekdays together with days or months is not supported. Each time of
+/// day is its own trigger, unless the minutes repeat evenly through every hour.
+fn calendar_triggers(expression: &str) -> Option<Vec<String>> {
+  let (schedule, _) = parse_cron(expression).ok()?;
+  let fields: Vec<&str> = schedule.split_whitespace().collect();
+  let [second, minute, hour, day, month, weekday] = fields[..] else {
+    return None;
+  };
+  if expand_cron_field(second, 0, 59, &[])? != [0] {
+    return None;
+  }
+  let minutes = expand_cron_field(minute, 0, 59, &[])?;
+  let hours = expand_cron_field(hour, 0, 23, &[])?;
+  let days = expand_cron_field(day, 1, 31, &[])?;
+  let months = expand_cron_field(month, 1, 12, &MONTHS)?;
+  let mut weekdays: Vec<u32> = expand_cron_field(weekday, 0, 7, &WEEKDAYS)?.into_iter().map(|day| day % 7).collect();
+  weekdays.sort_unstable();
+  weekdays.dedup();
+
+  let every_day = days.len() == 31 && months.len() == 12;
+  let calendar = match (every_day, weekdays.len() == 7) {
+    (true, true) => "      <ScheduleByDay>\n        <DaysInterval>1</DaysInterval>\n      </ScheduleByDay>\n".to_string(),
+    (true, false) => {
+      let names: String = weekdays.iter().map(|day| format!("<{}/>", WEEKDAY_ELEMENTS[*day as usize])).collect();
+      format!("      <ScheduleByWeek>\n        <WeeksInterval>1</WeeksInterval>\n        <DaysOfWeek>{}</DaysOfWeek>\n      </ScheduleByWeek>\n", names)
+    }
+    (false, true) => {
+      let days: String = days.iter().map(|day| format!("<Day>{}</Day>", day)).collect();
+      let months: String = months.iter().map(|month| format!("<{}/>", MONTH_ELEMENTS[*month as usize - 1])).collect();
+      format!("      <ScheduleByMonth>\n        <DaysOfMonth>{}</DaysOfMonth>\n        <Months>{}</Months>\n      </ScheduleByMonth>\n", days, months)
+    }
+    (false, false) => return None,
+  };
+
+  // minutes repeating evenly through every hour are a single trigger repeated all day long
+  let step = minutes.get(1).map(|second| second - minutes[0]).unwrap_or(60);
+  let times: Vec<(u32, u32, Option<u32>)> = if hours.len() == 24 && minutes.len() > 1 && 60 % step == 0
+    && minutes.len() as u32 == 60 / step && minutes.windows(2).all(|pair| pair[1] - pair[0] == step)
+  {
+    vec![(0, minutes[0], Some(step))]
+  } else {
+    hours.iter().flat_map(|hour| minutes.iter().map(move |minute| (*hour, *minute, None))).collect()
+  };
+  if times.len() > MAX_TRIGGERS {
+    return None;
+  }
+
+  Some(times.into_iter().map(|(hour, minute, repetition)| {
+    let mut trigger = String::from("    <CalendarTrigger>\n");
+    if let Some(repetition) = repetition {
+      trigger.push_str(&format!(
+        "      <Repetition>\n        <Interval>PT{}M</Interval>\n        <Duration>P1D</Duration>\n      </Repetition>\n",
+        repetition
+      ));
+    }
+    trigger.push_str(&format!("      <StartBoundary>{}T{:02}:{:02}:00</StartBoundary>\n", CALENDAR_START_DATE, hour, minute));
+    trigger.push_str("      <Enabled>true</Enabled>\n");
+    trigger.push_str(&calendar);
+    trigger.push_str("    </CalendarTrigger>\n");
+    trigger
+  }).collect())
+}
+
+/// Task Scheduler weekday elements, from 0 (Sunday).
+const WEEKDAY_ELEMENTS: [&str; 7] = ["Sunday", "Monday", "Tuesday", "Wednesday", "Thursday", "Friday", "Saturday"];
+/// Task Scheduler month elements, from January.
+const MONTH_ELEMENTS: [&str; 12] = [
+  "January", "February", "March", "April", "May", "June",
+  "July", "August", "September", "October", "November", "December",
+];
+
+/// Quotes an argument the way `CommandLineToArgvW` splits it back.
+fn quote_arg(arg: &str) -> String {
+  let mut quoted = String::from("\"");
+  let mut backslashes = 0;
+  for c in arg.chars() {
+    match c {
+      '\\' => backslashes += 1,
+      '"' => {
+        quoted.push_str(&"\\".repeat(backslashes * 2 + 1));
+        quoted.push('"');
+        backslashes = 0;
+      }
+      c => {
+        quoted.push_str(&"\\".repeat(backslashes));
+        quoted.push(c);
+        backslashes = 0;
+      }
+    }
+  }
+  quoted.push_str(&"\\".repeat(backslashes * 2));
+  quoted.push('"');
+  quoted
+}
+
+/// Splits arguments quoted by [`quote_arg`].
+fn split_args(line: &str) -> Vec<String> {
+  let mut args = Vec::new();
+  let mut arg: Option<String> = None;
+  let mut quoted = false;
+  let mut backslashes = 0;
+  for c in line.chars() {
+    match c {
+      '\\' => backslashes += 1,
+      '"' => {
+        let arg = arg.get_or_insert_with(String::new);
+        arg.push_str(&"\\".repeat(backslashes / 2));
+        if backslashes % 2 == 1 {
+          arg.push('"');
+        } else {
+          quoted = !quoted;
+        }
+        backslashes = 0;
+      }
+      c => {
+        if backslashes > 0 {
+          arg.get_or_insert_with(String::new).push_str(&"\\".repeat(backslashes));
+          backslashes = 0;
+        }
+        if c.is_whitespace() && !quoted {
+          args.extend(arg.take());
+        } else {
+          arg.get_or_insert_with(String::new).push(c);
+        }
+      }
+    }
+  }
+  if backslashes > 0 {
+    arg.get_or_insert_with(String::new).push_str(&"\\".repeat(backslashes));
+  }
+  args.extend(arg);
+  args
+}
+
+fn escape(value: &str) -> String {
+  value.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+fn unescape(value: &str) -> String {
+  value.replace("&quot;", "\"").replace("&lt;", "<").replace("&gt;", ">").replace("&amp;", "&")
+}
+
+/// The text of the first `element` of an XML definition.
+fn xml_value(xml: &str, element: &str) -> Option<String> {
+  let start = xml.find(&format!("<{}>", element))? + element.len() + 2;
+  let end = xml[start..].find(&format!("</{}>", element))?;
+  Some(unescape(&xml[start..start + end]))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn render(schedule: ScheduleTime) -> String {
+    let args = ["--run-task=backup", "--task-param=target=C:\\Backups \"nightly\""];
+    let task = installed_task("1", schedule, &args, "taskScheduler").unwrap();
+    let command = TaskCommand::new("C:\\Program Files\\App\\app.exe", &task);
+    render_task_xml(&task, &command, false).unwrap()
+  }
+
+  #[test]
+  fn renders_one_time_tasks() {
+    let xml = render(ScheduleTime::DateTime("2026-03-01T09:30:00+01:00".to_string()));
+    assert_eq!(xml, include_str!("golden/one_time.xml"));
+  }
+
+  #[test]
+  fn renders_intervals_as_repetitions() {
+    let xml = render(ScheduleTime::Interval(IntervalSchedule {
+      every: 900,
+      start_at: Some("2026-03-01T08:00:00Z".to_string()),
+      end_at: Some("2026-03-31T18:00:00Z".to_string()),
+      max_runs: None,
+    }));
+    assert_eq!(xml, include_str!("golden/interval.xml"));
+  }
+
+  #[test]
+  fn renders_weekly_cron_schedules() {
+    let xml = render(ScheduleTime::Cron("30 9,17 * * MON,WED,FRI".to_string()));
+    assert_eq!(xml, include_str!("golden/weekly.xml"));
+  }
+
+  #[test]
+  fn renders_monthly_cron_schedules() {
+    let xml = render(ScheduleTime::Cron("0 6 1,15 JAN,JUL *".to_string()));
+    assert_eq!(xml, include_str!("golden/monthly.xml"));
+  }
+
+  #[test]
+  fn renders_minute_steps_as_a_daily_repetition() {
+    let xml = render(ScheduleTime::Cron("*/15 * * * *".to_string()));
+    assert_eq!(xml, include_str!("golden/every_quarter_hour.xml"));
+  }
+
+  #[test]
+  fn rejects_weekdays_with_days_of_month() {
+    assert!(triggers(&ScheduleTime::Cron("0 6 1 * MON".to_string())).is_none());
+  }
+
+  #[test]
+  fn lists_installed_arguments_back() {
+    let xml = render(ScheduleTime::Cron("0 6 1 * *".to_string()));
+    let args = split_args(&xml_value(&xml, "Arguments").unwrap());
+    assert_eq!(args, ["--task-id=1", "--run-task=backup", "--task-param=target=C:\\Backups \"nightly\""]);
+  }
+
+  /// Installs the same task twice with a `schtasks` script logging the definitions it can read.
+  #[test]
+  #[cfg(unix)]
+  fn writes_each_definition_to_a_file_of_its_own() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = std::env::temp_dir().join(format!("schedule-task-schtasks-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    let script = dir.join("schtasks");
+    std::fs::write(&script, "#!/bin/sh\ntest -f \"$3\" && echo \"$3\" >> \"$(dirname \"$0\")/schtasks.log\"\n").unwrap();
+    std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+    let task = installed_task("1", ScheduleTime::Cron("0 6 1 * *".to_string()), &["--run-task=backup"], "taskScheduler").unwrap();
+    let command = TaskCommand::new("C:\\Program Files\\App\\app.exe", &task);
+    let scheduler = WindowsTaskScheduler::new("com.example.app").with_schtasks(&script);
+    scheduler.install(&task, &command).unwrap();
+    scheduler.install(&task, &command).unwrap();
+
+    let log = std::fs::read_to_string(dir.join("schtasks.log")).unwrap();
+    let paths: Vec<&str> = log.lines().collect();
+    assert_eq!(paths.len(), 2);
+    assert_ne!(paths[0], paths[1]);
+    for path in paths {
+      assert!(path.contains("schedule-task-com.example.app-1-"));
+      assert!(!std::path::Path::new(path).exists());
+    }
+  }
+}
@@ -0,0 +1,77 @@
+//! IANA time zones of tasks, and how the local times a DST change skips or repeats are read.
+
+use chrono::{DateTime, Duration, LocalResult, NaiveDateTime, Offset, TimeZone, Utc};
+use chrono_tz::Tz;
+use std::str::FromStr;
+
+/// Parses an IANA time zone name, e.g. `Europe/Berlin`.
+pub(crate) fn parse_timezone(name: &str) -> crate::Result<Tz> {
+  name.parse().map_err(|_| crate::Error::UnknownTimezone(name.to_string()))
+}
+
+/// The instant a local time of `zone` stands for.
+///
+/// A local time repeated when the clocks go back is its first occurrence. A local time skipped
+/// when they go forward is read with the offset in force before the change (as RFC 5545 does), so
+/// 02:30 on a night the clocks jump from 02:00 to 03:00 is 03:30.
+pub(crate) fn from_local<Z: TimeZone>(zone: &Z, local: &NaiveDateTime) -> Option<DateTime<Z>> {
+  match zone.from_local_datetime(local) {
+    LocalResult::Single(at) => Some(at),
+    LocalResult::Ambiguous(first, _) => Some(first),
+    LocalResult::None => {
+      // DST gaps are much shorter than a day
+      let before = zone.from_local_datetime(&(*local - Duration::days(1))).earliest()?;
+      let offset = Duration::seconds(before.offset().fix().local_minus_utc() as i64);
+      Some(zone.from_utc_datetime(&(*local - offset)))
+    }
+  }
+}
+
+/// Parses an ISO 8601 datetime, read as a local time of `zone` when it has no offset.
+pub(crate) fn parse_datetime_in(dt_str: &str, zone: Option<&Tz>) -> crate::Result<DateTime<Utc>> {
+  let invalid = |e: chrono::ParseError| crate::Error::Generic(format!("Invalid datetime format: {}", e));
+  match (DateTime::<Utc>::from_str(dt_str), zone) {
+    (Ok(dt), _) => Ok(dt),
+    (Err(e), None) => Err(invalid(e)),
+    (Err(_), Some(zone)) => {
+      let local = NaiveDateTime::from_str(dt_str).map_err(invalid)?;
+      from_local(zone, &local)
+        .map(|dt| dt.with_timezone(&Utc))
+        .ok_or_else(|| crate::Error::Generic(format!("Datetime {} does not exist in {}", dt_str, zone)))
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn berlin(local: &str) -> String {
+    let local = NaiveDateTime::from_str(local).unwrap();
+    from_local(&parse_timezone("Europe/Berlin").unwrap(), &local).unwrap().to_rfc3339()
+  }
+
+  #[test]
+  fn moves_skipped_local_times_forward() {
+    // the clocks go from 02:00 to 03:00 on 2026-03-29
+    assert_eq!(berlin("2026-03-29T02:30:00"), "2026-03-29T03:30:00+02:00");
+    assert_eq!(berlin("2026-03-29T01:59:00"), "2026-03-29T01:59:00+01:00");
+    assert_eq!(berlin("2026-03-29T03:00:00"), "2026-03-29T03:00:00+02:00");
+  }
+
+  #[test]
+  fn reads_repeated_local_times_as_their_first_occurrence() {
+    // the clocks go from 03:00 back to 02:00 on 2026-10-25
+    assert_eq!(berlin("2026-10-25T02:30:00"), "2026-10-25T02:30:00+02:00");
+    assert_eq!(berlin("2026-10-25T03:00:00"), "2026-10-25T03:00:00+01:00");
+  }
+
+  #[test]
+  fn parses_datetimes_without_offset_in_the_zone() {
+    let zone = parse_timezone("Europe/Berlin").unwrap();
+    assert_eq!(parse_datetime_in("2026-03-29T02:30:00", Some(&zone)).unwrap().to_rfc3339(), "2026-03-29T01:30:00+00:00");
+    assert_eq!(parse_datetime_in("2026-03-29T02:30:00Z", Some(&zone)).unwrap().to_rfc3339(), "2026-03-29T02:30:00+00:00");
+    assert!(parse_datetime_in("2026-03-29T02:30:00", None).is_err());
+    assert!(matches!(parse_timezone("Europe/Atlantis"), Err(crate::Error::UnknownTimezone(_))));
+  }
+}